@@ -2,7 +2,7 @@
 #[global_allocator]
 static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 #[cfg(feature = "daemon")]
 use validator::daemon;
 use validator::{custom_rules, validate};
@@ -23,9 +23,17 @@ impl std::fmt::Display for OutputFormat {
     }
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Prints the JSON Schema of the validation output
+    Schema,
+}
+
 #[derive(Parser, Debug)]
 #[command(name = "gtfs-validator", about = "Validates the gtfs file.")]
 struct Opt {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(
         short,
         long = "input",
@@ -53,11 +61,28 @@ struct Opt {
         help = "Provide a YAML file to customize some validation rules"
     )]
     custom_rules: Option<String>,
+    #[cfg(feature = "daemon")]
+    #[arg(
+        long = "config",
+        help = "Configuration file of the dæmon (YAML, or TOML with a .toml extension)"
+    )]
+    config: Option<std::path::PathBuf>,
 }
 
 fn main() -> Result<(), anyhow::Error> {
+    let opt = Opt::parse();
+    // the dæmon configuration is read first, as it sets up the logs
+    #[cfg(feature = "daemon")]
+    let config = match (&opt.input, &opt.command) {
+        (None, None) => Some(daemon::Config::load(opt.config.as_deref())?),
+        _ => None,
+    };
     #[cfg(feature = "daemon")]
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    env_logger::Builder::from_env(
+        env_logger::Env::default()
+            .default_filter_or(config.as_ref().map_or("info", |c| c.server.log.as_str())),
+    )
+    .init();
     log::info!(
         "Allocator: {}",
         if cfg!(feature = "jemalloc") {
@@ -67,7 +92,13 @@ fn main() -> Result<(), anyhow::Error> {
         }
     );
 
-    let opt = Opt::parse();
+    if let Some(Command::Schema) = opt.command {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&validate::response_schema())?
+        );
+        return Ok(());
+    }
     let custom_rules = custom_rules::custom_rules(opt.custom_rules);
 
     if let Some(input) = opt.input {
@@ -80,9 +111,9 @@ fn main() -> Result<(), anyhow::Error> {
         println!("{}", serialized);
     } else {
         #[cfg(feature = "daemon")]
-        {
+        if let Some(config) = config {
             log::info!("Starting the validator as a dæmon");
-            daemon::run_server()?;
+            daemon::run_server(config)?;
         }
         #[cfg(not(feature = "daemon"))]
         {
@@ -14,6 +14,19 @@ pub struct CustomRules {
     pub max_air_speed: Option<f64>,
     pub max_taxi_speed: Option<f64>,
     pub max_other_speed: Option<f64>,
+    pub reference_date: Option<chrono::NaiveDate>,
+    pub expiration_warning_days: Option<u32>,
+    pub max_days_before_start: Option<u32>,
+    pub service_drop_ratio: Option<f64>,
+    pub max_stop_shape_distance: Option<f64>,
+}
+
+impl CustomRules {
+    /// The date the validity of the feed is checked against, today if not given.
+    pub fn reference_date(&self) -> chrono::NaiveDate {
+        self.reference_date
+            .unwrap_or_else(|| chrono::Local::now().date_naive())
+    }
 }
 
 pub fn custom_rules(file_path: Option<String>) -> CustomRules {
@@ -47,6 +60,18 @@ fn test_some_custom_rules() {
     assert_eq!(None, custom_rules.max_air_speed);
 }
 
+#[test]
+fn test_feed_validity_rules() {
+    let file_path = Some(String::from("test_data/feed_validity/custom_rules.yml"));
+    let custom_rules = custom_rules(file_path);
+    assert_eq!(
+        chrono::NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+        custom_rules.reference_date()
+    );
+    assert_eq!(Some(15), custom_rules.expiration_warning_days);
+    assert_eq!(None, custom_rules.max_days_before_start);
+}
+
 #[test]
 #[should_panic(expected = "Could not open custom-rules file")]
 fn test_no_file() {
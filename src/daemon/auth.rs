@@ -0,0 +1,428 @@
+//! Optional authentication of the clients by API keys, with per-key rate limits and daily quotas.
+//!
+//! Without any configured key, the dæmon stays open to everyone.
+use super::env_or;
+use super::metrics::Metrics;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, header};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse, ResponseError, web};
+use anyhow::Context;
+use chrono::{NaiveDate, Utc};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Paths that can be called without a key.
+///
+/// The metrics are not public, as they are labelled with the names of the clients.
+const PUBLIC_PATHS: [&str; 5] = ["/", "/health", "/ready", "/version", "/openapi.json"];
+
+/// An API key, as configured in the `API_KEYS_FILE`.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKey {
+    /// Identity of the client, used in the logs and the metrics.
+    pub name: String,
+    pub key: String,
+    /// Maximum number of requests per minute.
+    pub rate_limit: Option<u32>,
+    /// Maximum number of validations per day (UTC).
+    pub daily_quota: Option<u32>,
+}
+
+struct Usage {
+    /// Requests that can still be made, refilled continuously up to the rate limit.
+    tokens: f64,
+    refilled_at: Instant,
+    day: NaiveDate,
+    validations: u32,
+}
+
+struct Client {
+    name: String,
+    rate_limit: Option<u32>,
+    daily_quota: Option<u32>,
+    usage: Mutex<Usage>,
+}
+
+/// Settings of the API keys.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiKeySettings {
+    /// YAML file listing API keys.
+    pub file: Option<PathBuf>,
+    pub keys: Vec<ApiKey>,
+    /// Rate limit of the keys that do not set their own.
+    pub rate_limit: Option<u32>,
+    /// Daily quota of the keys that do not set their own.
+    pub daily_quota: Option<u32>,
+}
+
+impl ApiKeySettings {
+    /// Overrides the settings with the environment variables.
+    ///
+    /// The keys of `API_KEYS`, a comma separated list of `name:key`, are added to the others.
+    pub fn with_env(mut self) -> Self {
+        self.keys.extend(
+            std::env::var("API_KEYS")
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(|k| k.trim().split_once(':'))
+                .map(|(name, key)| ApiKey {
+                    name: name.to_owned(),
+                    key: key.to_owned(),
+                    rate_limit: None,
+                    daily_quota: None,
+                }),
+        );
+        let limit = |name, value: Option<u32>| {
+            Some(env_or(name, value.unwrap_or_default())).filter(|l| *l > 0)
+        };
+        ApiKeySettings {
+            file: std::env::var("API_KEYS_FILE")
+                .ok()
+                .map(PathBuf::from)
+                .or(self.file),
+            keys: self.keys,
+            rate_limit: limit("API_RATE_LIMIT", self.rate_limit),
+            daily_quota: limit("API_DAILY_QUOTA", self.daily_quota),
+        }
+    }
+}
+
+/// Why a request has been rejected.
+#[derive(Debug, PartialEq)]
+pub enum Denied {
+    /// No key, or an unknown one, has been given.
+    Unauthorized,
+    /// The rate limit of the key is exceeded, the request can be retried after this delay.
+    RateLimited(Duration),
+    QuotaExceeded,
+}
+
+impl fmt::Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Denied::Unauthorized => write!(f, "a valid API key is needed"),
+            Denied::RateLimited(_) => write!(f, "too many requests, retry later"),
+            Denied::QuotaExceeded => write!(f, "the daily quota of validations is exceeded"),
+        }
+    }
+}
+
+impl ResponseError for Denied {
+    fn error_response(&self) -> HttpResponse {
+        let mut response = match self {
+            Denied::Unauthorized => {
+                let mut response = HttpResponse::Unauthorized();
+                response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+                response
+            }
+            Denied::RateLimited(retry_after) => {
+                let mut response = HttpResponse::TooManyRequests();
+                response.insert_header((header::RETRY_AFTER, retry_after.as_secs().max(1)));
+                response
+            }
+            Denied::QuotaExceeded => HttpResponse::TooManyRequests(),
+        };
+        response.body(self.to_string())
+    }
+}
+
+/// The API keys, indexed by their SHA-256 hash so the keys themselves are not kept.
+#[derive(Default)]
+pub struct ApiKeys(HashMap<String, Client>);
+
+impl ApiKeys {
+    /// The keys of the settings and of their file.
+    ///
+    /// Fails if the file cannot be read: the dæmon must not be open by mistake.
+    pub fn from_settings(settings: ApiKeySettings) -> anyhow::Result<Self> {
+        let mut keys = settings.keys;
+        if let Some(path) = &settings.file {
+            let file = std::fs::File::open(path)
+                .with_context(|| format!("impossible to read the API keys {:?}", path))?;
+            let file_keys: Vec<ApiKey> = serde_norway::from_reader(file)
+                .with_context(|| format!("invalid API keys file {:?}", path))?;
+            keys.extend(file_keys);
+        }
+        for key in &mut keys {
+            key.rate_limit = key.rate_limit.or(settings.rate_limit);
+            key.daily_quota = key.daily_quota.or(settings.daily_quota);
+        }
+        if !keys.is_empty() {
+            log::info!("{} API keys loaded, requests need one of them", keys.len());
+        }
+        Ok(Self::new(keys))
+    }
+
+    pub fn new(keys: Vec<ApiKey>) -> Self {
+        ApiKeys(
+            keys.into_iter()
+                .map(|key| {
+                    let usage = Usage {
+                        tokens: key.rate_limit.unwrap_or_default() as f64,
+                        refilled_at: Instant::now(),
+                        day: Utc::now().date_naive(),
+                        validations: 0,
+                    };
+                    let client = Client {
+                        name: key.name,
+                        rate_limit: key.rate_limit,
+                        daily_quota: key.daily_quota,
+                        usage: Mutex::new(usage),
+                    };
+                    (hash(&key.key), client)
+                })
+                .collect(),
+        )
+    }
+
+    /// True if no key is configured: every request is accepted.
+    pub fn is_open(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Checks the key of a request, counting it in the usage of the key.
+    ///
+    /// Returns the identity of the client.
+    pub fn check(&self, key: Option<&str>, validation: bool) -> Result<&str, Denied> {
+        let client = key
+            .and_then(|k| self.0.get(&hash(k)))
+            .ok_or(Denied::Unauthorized)?;
+        let mut usage = client.usage.lock().unwrap();
+        let today = Utc::now().date_naive();
+        if usage.day != today {
+            usage.day = today;
+            usage.validations = 0;
+        }
+        if validation
+            && client
+                .daily_quota
+                .is_some_and(|quota| usage.validations >= quota)
+        {
+            return Err(Denied::QuotaExceeded);
+        }
+        if let Some(rate_limit) = client.rate_limit {
+            let per_second = rate_limit as f64 / 60.;
+            let now = Instant::now();
+            let elapsed = now.duration_since(usage.refilled_at).as_secs_f64();
+            usage.tokens = (usage.tokens + elapsed * per_second).min(rate_limit as f64);
+            usage.refilled_at = now;
+            if usage.tokens < 1. {
+                let retry_after = (1. - usage.tokens) / per_second;
+                return Err(Denied::RateLimited(Duration::from_secs_f64(
+                    retry_after.ceil(),
+                )));
+            }
+            usage.tokens -= 1.;
+        }
+        if validation {
+            usage.validations += 1;
+        }
+        Ok(&client.name)
+    }
+
+    /// Gives back the validation counted by `check` for a request that has failed.
+    pub fn refund(&self, key: &str) {
+        if let Some(client) = self.0.get(&hash(key)) {
+            let mut usage = client.usage.lock().unwrap();
+            usage.validations = usage.validations.saturating_sub(1);
+        }
+    }
+}
+
+fn hash(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// The key of the request, from an `Authorization: Bearer` or a `X-API-Key` header.
+fn request_key(req: &ServiceRequest) -> Option<&str> {
+    let headers = req.headers();
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .or_else(|| headers.get("X-API-Key").and_then(|h| h.to_str().ok()))
+        .map(str::trim)
+}
+
+/// True if the request asks for a validation, counted in the daily quota.
+fn is_validation(req: &ServiceRequest) -> bool {
+    req.path() == "/validate" || (req.path() == "/jobs" && req.method() == Method::POST)
+}
+
+/// Middleware rejecting the requests without a valid key, when keys are configured.
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let keys = req.app_data::<web::Data<ApiKeys>>().cloned();
+    // the validation counted in the quota of the client, given back if it fails
+    let mut counted = None;
+    if let Some(keys) = keys
+        && !keys.is_open()
+        && !PUBLIC_PATHS.contains(&req.path())
+    {
+        let metrics = req.app_data::<web::Data<Metrics>>().cloned();
+        let checked = keys.check(request_key(&req), is_validation(&req));
+        let (name, outcome) = match &checked {
+            Ok(name) => (*name, "accepted"),
+            Err(Denied::Unauthorized) => ("", "unauthorized"),
+            Err(Denied::RateLimited(_)) => (request_name(&keys, &req), "rate_limited"),
+            Err(Denied::QuotaExceeded) => (request_name(&keys, &req), "quota_exceeded"),
+        };
+        if let Some(metrics) = metrics {
+            metrics.observe_api_request(name, outcome);
+        }
+        log::info!(
+            "{} {} by {}: {}",
+            req.method(),
+            req.path(),
+            if name.is_empty() {
+                "unknown client"
+            } else {
+                name
+            },
+            outcome
+        );
+        if let Err(denied) = checked {
+            let response = denied.error_response();
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+        if is_validation(&req)
+            && let Some(key) = request_key(&req)
+        {
+            counted = Some((keys.clone(), key.to_owned()));
+        }
+    }
+    let response = next.call(req).await;
+    if let Some((keys, key)) = counted
+        && response.as_ref().map_or(true, |r| {
+            r.status().is_client_error() || r.status().is_server_error()
+        })
+    {
+        keys.refund(&key);
+    }
+    response.map(ServiceResponse::map_into_left_body)
+}
+
+/// Name of the client of a request with a known key.
+fn request_name<'a>(keys: &'a ApiKeys, req: &ServiceRequest) -> &'a str {
+    request_key(req)
+        .and_then(|k| keys.0.get(&hash(k)))
+        .map_or("", |c| c.name.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{TestRequest, call_service, init_service};
+    use actix_web::{App, middleware::from_fn};
+
+    fn keys() -> ApiKeys {
+        ApiKeys::new(vec![
+            ApiKey {
+                name: "limited".to_owned(),
+                key: "limited-key".to_owned(),
+                rate_limit: Some(3),
+                daily_quota: Some(2),
+            },
+            ApiKey {
+                name: "unlimited".to_owned(),
+                key: "unlimited-key".to_owned(),
+                rate_limit: None,
+                daily_quota: None,
+            },
+        ])
+    }
+
+    #[test]
+    fn test_check() {
+        let keys = keys();
+        assert_eq!(Err(Denied::Unauthorized), keys.check(None, false));
+        assert_eq!(Err(Denied::Unauthorized), keys.check(Some("wrong"), false));
+        assert_eq!(Ok("limited"), keys.check(Some("limited-key"), true));
+        assert_eq!(Ok("limited"), keys.check(Some("limited-key"), true));
+        assert_eq!(
+            Err(Denied::QuotaExceeded),
+            keys.check(Some("limited-key"), true)
+        );
+        assert_eq!(Ok("limited"), keys.check(Some("limited-key"), false));
+        // 3 requests per minute: a new one is allowed every 20 seconds
+        assert_eq!(
+            Err(Denied::RateLimited(Duration::from_secs(20))),
+            keys.check(Some("limited-key"), false)
+        );
+        for _ in 0..10 {
+            assert_eq!(Ok("unlimited"), keys.check(Some("unlimited-key"), true));
+        }
+        assert!(ApiKeys::default().is_open());
+    }
+
+    #[actix_web::test]
+    async fn test_middleware() {
+        let metrics = web::Data::new(Metrics::new());
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(keys()))
+                .app_data(metrics.clone())
+                .wrap(from_fn(authenticate))
+                .route("/health", web::get().to(HttpResponse::Ok))
+                .route("/metrics", web::get().to(HttpResponse::Ok))
+                .route("/validate", web::get().to(HttpResponse::Ok))
+                .route("/jobs", web::post().to(HttpResponse::ServiceUnavailable)),
+        )
+        .await;
+
+        let req = TestRequest::get().uri("/health").to_request();
+        assert_eq!(StatusCode::OK, call_service(&app, req).await.status());
+        for uri in ["/validate", "/metrics"] {
+            let req = TestRequest::get().uri(uri).to_request();
+            assert_eq!(
+                StatusCode::UNAUTHORIZED,
+                call_service(&app, req).await.status()
+            );
+        }
+        // a failed validation is not counted in the quota
+        let req = TestRequest::post()
+            .uri("/jobs")
+            .insert_header(("X-API-Key", "limited-key"))
+            .to_request();
+        assert_eq!(
+            StatusCode::SERVICE_UNAVAILABLE,
+            call_service(&app, req).await.status()
+        );
+        let req = TestRequest::get()
+            .uri("/validate")
+            .insert_header((header::AUTHORIZATION, "Bearer limited-key"))
+            .to_request();
+        assert_eq!(StatusCode::OK, call_service(&app, req).await.status());
+        for status in [StatusCode::OK, StatusCode::TOO_MANY_REQUESTS] {
+            let req = TestRequest::get()
+                .uri("/validate")
+                .insert_header(("X-API-Key", "limited-key"))
+                .to_request();
+            assert_eq!(status, call_service(&app, req).await.status());
+        }
+
+        let metrics = metrics.encode();
+        assert!(
+            metrics
+                .contains("validator_api_requests_total{key=\"limited\",outcome=\"accepted\"} 3")
+        );
+        assert!(metrics.contains(
+            "validator_api_requests_total{key=\"limited\",outcome=\"quota_exceeded\"} 1"
+        ));
+        assert!(
+            metrics.contains("validator_api_requests_total{key=\"\",outcome=\"unauthorized\"} 2")
+        );
+    }
+}
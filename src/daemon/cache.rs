@@ -0,0 +1,398 @@
+//! Cache of the validation results.
+//!
+//! The same feeds are validated many times, so the serialized [Response] is cached, keyed
+//! by a hash of the archive, of the validation parameters and of the validator version.
+//! The cache is a LRU in memory, optionally backed by a directory.
+//!
+//! [Response]: ../../validate/struct.Response.html
+use super::auth::ApiKeys;
+use super::env_or;
+use super::metrics::Metrics;
+use super::report::OutputFormat;
+use crate::custom_rules::CustomRules;
+use crate::progress::Progress;
+use crate::validate::generate_validation_from_reader_with_progress;
+use actix_web::http::header::{self, CacheControl, CacheDirective, EntityTag};
+use actix_web::{Error, HttpMessage, HttpRequest, HttpResponse, web, web::Bytes};
+use lru::LruCache;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Settings of the results cache.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheSettings {
+    /// Number of results kept in memory. The cache is disabled if 0.
+    pub size: usize,
+    /// When set, results are also stored in this directory.
+    pub dir: Option<PathBuf>,
+    /// Number of results kept in the directory.
+    pub dir_size: usize,
+    /// `max-age` of the `Cache-Control` header of the responses, in seconds.
+    pub max_age: u32,
+}
+
+impl Default for CacheSettings {
+    fn default() -> Self {
+        CacheSettings {
+            size: 50,
+            dir: None,
+            dir_size: 1000,
+            max_age: 0,
+        }
+    }
+}
+
+impl CacheSettings {
+    /// Overrides the settings with the environment variables.
+    pub fn with_env(self) -> Self {
+        CacheSettings {
+            size: env_or("CACHE_SIZE", self.size),
+            dir: std::env::var("CACHE_DIR")
+                .ok()
+                .map(PathBuf::from)
+                .or(self.dir),
+            dir_size: env_or("CACHE_DIR_SIZE", self.dir_size),
+            max_age: env_or("CACHE_MAX_AGE", self.max_age),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    /// The result was found in the cache.
+    Hit,
+    /// The result was not in the cache, the GTFS has been validated.
+    Miss,
+    /// The cache has not been read as asked by the client, the GTFS has been validated.
+    Bypass,
+}
+
+impl CacheStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "HIT",
+            CacheStatus::Miss => "MISS",
+            CacheStatus::Bypass => "BYPASS",
+        }
+    }
+}
+
+/// A serialized [Response], with the key used to cache it.
+///
+/// [Response]: ../../validate/struct.Response.html
+pub struct CachedResult {
+    pub key: String,
+    pub json: Bytes,
+    pub status: CacheStatus,
+}
+
+pub struct ResultCache {
+    memory: Option<Mutex<LruCache<String, Bytes>>>,
+    dir: Option<PathBuf>,
+    dir_size: usize,
+    max_age: u32,
+    metrics: Arc<Metrics>,
+}
+
+impl ResultCache {
+    pub fn new(settings: CacheSettings, metrics: Arc<Metrics>) -> Self {
+        if let Some(dir) = &settings.dir
+            && let Err(e) = std::fs::create_dir_all(dir)
+        {
+            log::error!("impossible to create the cache directory {:?}: {}", dir, e);
+        }
+        ResultCache {
+            memory: NonZeroUsize::new(settings.size).map(|size| Mutex::new(LruCache::new(size))),
+            dir: settings.dir,
+            dir_size: settings.dir_size,
+            max_age: settings.max_age,
+            metrics,
+        }
+    }
+
+    /// Validates the archive, unless its result is already cached.
+    pub fn validate(
+        &self,
+        archive: &[u8],
+        max_size: usize,
+        custom_rules: &CustomRules,
+        bypass: bool,
+        progress: &impl Progress,
+    ) -> CachedResult {
+        let key = cache_key(archive, max_size, custom_rules);
+        if !bypass && let Some(json) = self.get(&key) {
+            self.metrics.observe_cache(CacheStatus::Hit);
+            return CachedResult {
+                key,
+                json,
+                status: CacheStatus::Hit,
+            };
+        }
+
+        let status = if bypass {
+            CacheStatus::Bypass
+        } else {
+            CacheStatus::Miss
+        };
+        self.metrics.observe_cache(status);
+        let start = Instant::now();
+        let response = generate_validation_from_reader_with_progress(
+            std::io::Cursor::new(archive),
+            max_size,
+            custom_rules,
+            progress,
+        );
+        self.metrics
+            .observe_validation(&response, archive.len(), start.elapsed());
+        let json = Bytes::from(serde_json::to_vec(&response).unwrap_or_default());
+        self.insert(&key, json.clone());
+        CachedResult { key, json, status }
+    }
+
+    fn get(&self, key: &str) -> Option<Bytes> {
+        if let Some(json) = self
+            .memory
+            .as_ref()
+            .and_then(|m| m.lock().unwrap().get(key).cloned())
+        {
+            return Some(json);
+        }
+        let path = self.path(key)?;
+        let json = Bytes::from(std::fs::read(&path).ok()?);
+        // we touch the file, for the least recently used files to be removed first
+        if let Err(e) = std::fs::File::options()
+            .append(true)
+            .open(&path)
+            .and_then(|f| f.set_modified(std::time::SystemTime::now()))
+        {
+            log::warn!("impossible to touch the cached file {:?}: {}", path, e);
+        }
+        if let Some(memory) = &self.memory {
+            memory.lock().unwrap().put(key.to_owned(), json.clone());
+        }
+        Some(json)
+    }
+
+    fn insert(&self, key: &str, json: Bytes) {
+        if let Some(memory) = &self.memory {
+            memory.lock().unwrap().put(key.to_owned(), json.clone());
+        }
+        if let Some(path) = self.path(key) {
+            match std::fs::write(&path, &json) {
+                Ok(()) => self.evict_files(),
+                Err(e) => log::error!("impossible to write the cached file {:?}: {}", path, e),
+            }
+        }
+    }
+
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|d| d.join(format!("{}.json", key)))
+    }
+
+    // Removes the least recently used files if there are too many of them
+    fn evict_files(&self) {
+        let Some(dir) = &self.dir else { return };
+        let mut files: Vec<_> = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|e| {
+                let modified = e.metadata().and_then(|m| m.modified()).ok()?;
+                Some((modified, e.path()))
+            })
+            .collect();
+        if files.len() > self.dir_size {
+            files.sort();
+            for (_, path) in &files[..files.len() - self.dir_size] {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    /// Builds the HTTP response of a cached result, in the requested format.
+    ///
+    /// The key is used as `ETag`, so a `304 Not Modified` is returned if the client
+    /// already has this result.
+    pub fn respond(
+        &self,
+        req: &HttpRequest,
+        result: CachedResult,
+        format: OutputFormat,
+    ) -> Result<HttpResponse, Error> {
+        let etag = match format {
+            OutputFormat::Json => EntityTag::new_strong(result.key),
+            _ => EntityTag::new_strong(format!("{}-{:?}", result.key, format).to_lowercase()),
+        };
+        let not_modified = match req.get_header::<header::IfNoneMatch>() {
+            Some(header::IfNoneMatch::Any) => true,
+            Some(header::IfNoneMatch::Items(tags)) => tags.iter().any(|t| t.weak_eq(&etag)),
+            None => false,
+        };
+        let mut builder = if not_modified {
+            HttpResponse::NotModified()
+        } else {
+            HttpResponse::Ok()
+        };
+        // with API keys, a shared cache must not give the result to the clients without key
+        let authenticated = req
+            .app_data::<web::Data<ApiKeys>>()
+            .is_some_and(|keys| !keys.is_open());
+        let visibility = if authenticated {
+            builder.insert_header((header::VARY, "Authorization, X-API-Key"));
+            CacheDirective::Private
+        } else {
+            CacheDirective::Public
+        };
+        builder
+            .insert_header(header::ETag(etag))
+            .insert_header(CacheControl(vec![
+                visibility,
+                CacheDirective::MaxAge(self.max_age),
+            ]))
+            .insert_header(("X-Cache", result.status.as_str()));
+        if not_modified {
+            Ok(builder.finish())
+        } else {
+            Ok(builder
+                .content_type(format.content_type())
+                .body(format.render(&result.json)?))
+        }
+    }
+}
+
+/// Hash of everything that has an influence on the validation result.
+fn cache_key(archive: &[u8], max_size: usize, custom_rules: &CustomRules) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    hasher.update([0]);
+    hasher.update(serde_json::to_vec(custom_rules).unwrap_or_default());
+    hasher.update([0]);
+    // the validity of the feed is checked against today, if no date is given
+    hasher.update(custom_rules.reference_date().to_string());
+    hasher.update([0]);
+    hasher.update(max_size.to_le_bytes());
+    hasher.update(archive);
+    format!("{:x}", hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::tests::zip_directory;
+
+    fn settings(size: usize, dir: Option<PathBuf>) -> CacheSettings {
+        CacheSettings {
+            size,
+            dir,
+            dir_size: 10,
+            max_age: 60,
+        }
+    }
+
+    #[test]
+    fn test_hit_and_bypass() {
+        let cache = ResultCache::new(settings(10, None), Arc::default());
+        let archive = zip_directory("test_data/unused_stop");
+        let rules = CustomRules::default();
+
+        let first = cache.validate(&archive, 1000, &rules, false, &());
+        assert_eq!(CacheStatus::Miss, first.status);
+        let second = cache.validate(&archive, 1000, &rules, false, &());
+        assert_eq!(CacheStatus::Hit, second.status);
+        assert_eq!(first.key, second.key);
+        assert_eq!(first.json, second.json);
+        let bypassed = cache.validate(&archive, 1000, &rules, true, &());
+        assert_eq!(CacheStatus::Bypass, bypassed.status);
+
+        // the parameters of the validation are part of the key
+        let rules = CustomRules {
+            max_bus_speed: Some(10.),
+            ..Default::default()
+        };
+        assert_eq!(
+            CacheStatus::Miss,
+            cache.validate(&archive, 1000, &rules, false, &()).status
+        );
+        assert_eq!(
+            CacheStatus::Miss,
+            cache.validate(&archive, 10, &rules, false, &()).status
+        );
+    }
+
+    #[test]
+    fn test_cache_control() {
+        use crate::daemon::auth::ApiKey;
+        use actix_web::test::TestRequest;
+
+        let cache = ResultCache::new(settings(10, None), Arc::default());
+        let archive = zip_directory("test_data/unused_stop");
+        let rules = CustomRules::default();
+        let respond = |req: HttpRequest| {
+            let result = cache.validate(&archive, 1000, &rules, false, &());
+            cache.respond(&req, result, OutputFormat::Json).unwrap()
+        };
+
+        let response = respond(TestRequest::default().to_http_request());
+        assert_eq!(
+            "public, max-age=60",
+            response.headers().get(header::CACHE_CONTROL).unwrap()
+        );
+
+        // the results given to the clients with a key are not shared
+        let keys = ApiKeys::new(vec![ApiKey {
+            name: "client".to_owned(),
+            key: "key".to_owned(),
+            rate_limit: None,
+            daily_quota: None,
+        }]);
+        let req = TestRequest::default()
+            .app_data(web::Data::new(keys))
+            .to_http_request();
+        let response = respond(req);
+        assert_eq!(
+            "private, max-age=60",
+            response.headers().get(header::CACHE_CONTROL).unwrap()
+        );
+        assert_eq!(
+            "Authorization, X-API-Key",
+            response.headers().get(header::VARY).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let cache = ResultCache::new(settings(1, None), Arc::default());
+        let rules = CustomRules::default();
+        let archive = zip_directory("test_data/unused_stop");
+        let other_archive = zip_directory("test_data/stops");
+
+        cache.validate(&archive, 1000, &rules, false, &());
+        cache.validate(&other_archive, 1000, &rules, false, &());
+        assert_eq!(
+            CacheStatus::Miss,
+            cache.validate(&archive, 1000, &rules, false, &()).status
+        );
+    }
+
+    #[test]
+    fn test_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = zip_directory("test_data/unused_stop");
+        let rules = CustomRules::default();
+
+        let cache = ResultCache::new(settings(10, Some(dir.path().to_owned())), Arc::default());
+        cache.validate(&archive, 1000, &rules, false, &());
+
+        // the results are still available after a restart
+        let cache = ResultCache::new(settings(0, Some(dir.path().to_owned())), Arc::default());
+        assert_eq!(
+            CacheStatus::Hit,
+            cache.validate(&archive, 1000, &rules, false, &()).status
+        );
+    }
+}
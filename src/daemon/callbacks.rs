@@ -0,0 +1,273 @@
+//! Notification of a `callback_url` when a validation job is finished.
+//!
+//! The notification is POSTed with retries and an exponential backoff. When a secret is
+//! configured, its body is signed with HMAC-SHA256 so the receiver can authenticate it.
+use super::config::seconds;
+use super::env_or;
+use super::jobs::{Job, JobStatus};
+use hmac::{Hmac, Mac};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Header holding the signature of the notification, as `sha256=<hex digest>`.
+pub const SIGNATURE_HEADER: &str = "X-Validator-Signature";
+
+/// Settings of the callbacks.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CallbackSettings {
+    /// Secret signing the notifications. They are not signed if not set.
+    pub secret: Option<String>,
+    /// Number of new attempts after a failed notification.
+    pub retries: u32,
+    /// Delay before the first retry, doubled after each attempt.
+    #[serde(deserialize_with = "seconds")]
+    pub backoff: Duration,
+    #[serde(deserialize_with = "seconds")]
+    pub timeout: Duration,
+}
+
+impl Default for CallbackSettings {
+    fn default() -> Self {
+        CallbackSettings {
+            secret: None,
+            retries: 5,
+            backoff: Duration::from_secs(2),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl CallbackSettings {
+    /// Overrides the settings with the environment variables.
+    pub fn with_env(self) -> Self {
+        CallbackSettings {
+            secret: std::env::var("CALLBACK_SECRET")
+                .ok()
+                .or(self.secret)
+                .filter(|s| !s.is_empty()),
+            retries: env_or("CALLBACK_RETRIES", self.retries),
+            backoff: Duration::from_secs(env_or("CALLBACK_BACKOFF", self.backoff.as_secs())),
+            timeout: Duration::from_secs(env_or("CALLBACK_TIMEOUT", self.timeout.as_secs())),
+        }
+    }
+}
+
+/// Where to notify the end of a job.
+pub struct Callback {
+    pub url: String,
+    /// Url of the dæmon, as seen by the client, used to link to the result.
+    pub base_url: String,
+}
+
+/// Body POSTed to the `callback_url`.
+#[derive(Serialize, JsonSchema, Debug)]
+pub struct Notification {
+    pub job: Job,
+    /// Where to get the validation result, if the job is finished.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result_url: Option<String>,
+    /// Number of issues found, by issue type.
+    pub issues_count: BTreeMap<String, usize>,
+}
+
+impl Notification {
+    pub fn new(job: Job, base_url: &str, result: Option<&str>) -> Self {
+        let result_url = (job.status == JobStatus::Finished)
+            .then(|| format!("{}/jobs/{}/result", base_url, job.id));
+        // the validations are truncated to max_size, the metadata counts all the issues
+        let issues_count = result
+            .and_then(|r| serde_json::from_str::<serde_json::Value>(r).ok())
+            .and_then(|mut r| serde_json::from_value(r["metadata"]["issues_count"].take()).ok())
+            .unwrap_or_default();
+        Notification {
+            job,
+            result_url,
+            issues_count,
+        }
+    }
+}
+
+/// Signature of the body, as sent in the [SIGNATURE_HEADER].
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+/// Sends the notification from a new thread, to not delay the next jobs.
+pub fn notify(settings: &Arc<CallbackSettings>, callback: Callback, notification: Notification) {
+    let settings = Arc::clone(settings);
+    std::thread::spawn(move || {
+        let body = match serde_json::to_vec(&notification) {
+            Ok(body) => body,
+            Err(e) => {
+                log::error!("impossible to serialize the notification: {}", e);
+                return;
+            }
+        };
+        if !deliver(&settings, &callback.url, body) {
+            log::warn!(
+                "the end of job {} could not be notified to {}",
+                notification.job.id,
+                callback.url
+            );
+        }
+    });
+}
+
+/// POSTs the body to the url until it answers with a success status.
+///
+/// Returns false if all the attempts have failed.
+/// The redirections are not followed, their target could be a host the url was not allowed to be.
+pub fn deliver(settings: &CallbackSettings, url: &str, body: Vec<u8>) -> bool {
+    let client = match reqwest::blocking::Client::builder()
+        .timeout(settings.timeout)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            log::error!("impossible to build the HTTP client: {}", e);
+            return false;
+        }
+    };
+    let signature = settings.secret.as_ref().map(|s| sign(s, &body));
+    let mut backoff = settings.backoff;
+    for attempt in 0..=settings.retries {
+        if attempt > 0 {
+            std::thread::sleep(backoff);
+            backoff *= 2;
+        }
+        let mut request = client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.clone());
+        if let Some(signature) = &signature {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+        match request.send() {
+            Ok(response) if response.status().is_success() => return true,
+            Ok(response) => log::info!(
+                "attempt {} to notify {} failed: status {}",
+                attempt + 1,
+                url,
+                response.status()
+            ),
+            Err(e) => log::info!("attempt {} to notify {} failed: {}", attempt + 1, url, e),
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc::{Receiver, channel};
+
+    /// A request received by the [stand_in] server.
+    pub struct Received {
+        pub headers: Vec<(String, String)>,
+        pub body: Vec<u8>,
+    }
+
+    impl Received {
+        pub fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        }
+    }
+
+    /// A local HTTP server answering the given statuses in turn, then `200`.
+    ///
+    /// Returns its url and the requests it receives.
+    pub fn stand_in(statuses: Vec<u16>) -> (String, Receiver<Received>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/callback", listener.local_addr().unwrap());
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || {
+            let mut statuses = statuses.into_iter();
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { return };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut headers = Vec::new();
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(':') {
+                        Some((name, value)) => {
+                            headers.push((name.to_owned(), value.trim().to_owned()))
+                        }
+                        None => break,
+                    }
+                }
+                let length = headers
+                    .iter()
+                    .find(|(n, _)| n.eq_ignore_ascii_case("content-length"))
+                    .map_or(0, |(_, v)| v.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let status = statuses.next().unwrap_or(200);
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Status\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+                if sender.send(Received { headers, body }).is_err() {
+                    return;
+                }
+            }
+        });
+        (url, receiver)
+    }
+
+    #[test]
+    fn test_sign() {
+        // from the test vectors of RFC 4231
+        assert_eq!(
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            sign("Jefe", b"what do ya want for nothing?")
+        );
+    }
+
+    #[test]
+    fn test_deliver() {
+        let settings = CallbackSettings {
+            secret: Some("secret".to_owned()),
+            retries: 2,
+            backoff: Duration::from_millis(10),
+            timeout: Duration::from_secs(5),
+        };
+        let (url, received) = stand_in(vec![500, 503]);
+        assert!(deliver(&settings, &url, b"{}".to_vec()));
+        for _ in 0..3 {
+            let request = received.recv().unwrap();
+            assert_eq!(b"{}".to_vec(), request.body);
+            assert_eq!(
+                Some(sign("secret", b"{}").as_str()),
+                request.header(SIGNATURE_HEADER)
+            );
+        }
+
+        let (url, received) = stand_in(vec![500, 500, 500]);
+        assert!(!deliver(&settings, &url, b"{}".to_vec()));
+        assert_eq!(3, received.iter().take(3).count());
+
+        // a redirection is not a delivery
+        let (url, received) = stand_in(vec![307, 307, 307]);
+        assert!(!deliver(&settings, &url, b"{}".to_vec()));
+        assert_eq!(3, received.iter().take(3).count());
+    }
+}
@@ -0,0 +1,170 @@
+//! Configuration of the dæmon.
+//!
+//! The settings are read from an optional YAML or TOML file, then overridden by the
+//! environment variables, each setting keeping its default value if it is set by neither.
+use super::auth::ApiKeySettings;
+use super::cache::CacheSettings;
+use super::env_or;
+use super::jobs::JobSettings;
+use super::limits::LimitSettings;
+use super::profiles::ProfileSettings;
+use anyhow::Context;
+use serde::{Deserialize, Deserializer};
+use std::path::Path;
+use std::time::Duration;
+
+/// Settings of the HTTP server.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    pub bind: String,
+    pub port: u16,
+    /// Number of HTTP workers, the number of cores if not set.
+    pub workers: Option<usize>,
+    /// On shutdown, how long the running validations are waited for.
+    #[serde(deserialize_with = "seconds")]
+    pub shutdown_timeout: Duration,
+    /// Default filter of the logs, `RUST_LOG` having precedence.
+    pub log: String,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            bind: "127.0.0.1".to_owned(),
+            port: 7878,
+            workers: None,
+            shutdown_timeout: Duration::from_secs(60),
+            log: "info".to_owned(),
+        }
+    }
+}
+
+impl ServerSettings {
+    /// Overrides the settings with the environment variables.
+    pub fn with_env(self) -> Self {
+        ServerSettings {
+            bind: env_or("BIND", self.bind),
+            port: env_or("PORT", self.port),
+            workers: std::env::var("WORKERS")
+                .ok()
+                .and_then(|w| w.parse().ok())
+                .or(self.workers),
+            shutdown_timeout: Duration::from_secs(env_or(
+                "SHUTDOWN_TIMEOUT",
+                self.shutdown_timeout.as_secs(),
+            )),
+            log: self.log,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerSettings,
+    pub limits: LimitSettings,
+    pub cache: CacheSettings,
+    pub jobs: JobSettings,
+    pub profiles: ProfileSettings,
+    pub api_keys: ApiKeySettings,
+}
+
+impl Config {
+    /// Reads the configuration file, given as parameter or by the `CONFIG_FILE` environment
+    /// variable, and overrides it with the environment variables.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let path = path
+            .map(Path::to_owned)
+            .or_else(|| std::env::var_os("CONFIG_FILE").map(Into::into));
+        let config = match path {
+            Some(path) => {
+                let content = std::fs::read_to_string(&path)
+                    .with_context(|| format!("impossible to read the configuration {:?}", path))?;
+                let toml = path.extension().is_some_and(|e| e == "toml");
+                Self::parse(&content, toml)
+                    .with_context(|| format!("invalid configuration {:?}", path))?
+            }
+            None => Config::default(),
+        };
+        Ok(config.with_env())
+    }
+
+    /// Parses a configuration, in TOML or in YAML.
+    pub fn parse(content: &str, toml: bool) -> anyhow::Result<Self> {
+        Ok(if toml {
+            toml::from_str(content)?
+        } else {
+            serde_norway::from_str(content)?
+        })
+    }
+
+    /// Overrides the settings with the environment variables.
+    pub fn with_env(self) -> Self {
+        Config {
+            server: self.server.with_env(),
+            limits: self.limits.with_env(),
+            cache: self.cache.with_env(),
+            jobs: self.jobs.with_env(),
+            profiles: self.profiles.with_env(),
+            api_keys: self.api_keys.with_env(),
+        }
+    }
+}
+
+/// Deserializes a duration given as a number of seconds.
+pub fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let yaml = r#"
+server:
+  port: 8080
+  shutdown_timeout: 10
+limits:
+  max_upload_size: 1000
+  denied_hosts: [localhost]
+cache:
+  dir: /tmp/cache
+jobs:
+  workers: 4
+  callbacks:
+    retries: 1
+profiles:
+  default: strict
+"#;
+        let config = Config::parse(yaml, false).unwrap();
+        assert_eq!(8080, config.server.port);
+        assert_eq!("127.0.0.1", config.server.bind);
+        assert_eq!(Duration::from_secs(10), config.server.shutdown_timeout);
+        assert_eq!(1000, config.limits.max_upload_size);
+        assert_eq!(500_000_000, config.limits.max_download_size);
+        assert_eq!(vec!["localhost"], config.limits.denied_hosts);
+        assert_eq!(Some("/tmp/cache".into()), config.cache.dir);
+        assert_eq!(4, config.jobs.workers);
+        assert_eq!(1, config.jobs.callbacks.retries);
+        assert_eq!("strict", config.profiles.default);
+
+        let toml = r#"
+[server]
+bind = "0.0.0.0"
+workers = 2
+
+[api_keys]
+rate_limit = 60
+"#;
+        let config = Config::parse(toml, true).unwrap();
+        assert_eq!("0.0.0.0", config.server.bind);
+        assert_eq!(Some(2), config.server.workers);
+        assert_eq!(Some(60), config.api_keys.rate_limit);
+
+        // typos are not silently ignored
+        assert!(Config::parse("server:\n  prot: 8080\n", false).is_err());
+    }
+}
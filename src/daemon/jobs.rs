@@ -0,0 +1,1056 @@
+//! Asynchronous validations.
+//!
+//! A job is created by `POST /jobs`, run by a bounded pool of worker threads, and its
+//! result is kept (in memory or in a directory) until it expires.
+use super::cache::ResultCache;
+use super::callbacks::{self, Callback, CallbackSettings, Notification};
+use super::config::seconds;
+use super::limits::{DownloadError, Limits};
+use super::metrics::Metrics;
+use super::profiles::Profiles;
+use super::{env_or, read_payload};
+use crate::custom_rules::CustomRules;
+use crate::progress::{self, Progress, Stage, Step};
+use crate::validate::process;
+use actix_web::http::header::{self, ContentType};
+use actix_web::{Error, HttpRequest, HttpResponse, error, get, post, web};
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Settings of the job queue.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobSettings {
+    /// Number of validations running in parallel.
+    pub workers: usize,
+    /// Maximum number of jobs waiting for a worker.
+    pub queue_size: usize,
+    /// How long a finished job is kept.
+    #[serde(deserialize_with = "seconds")]
+    pub ttl: Duration,
+    /// When set, jobs and their results are stored in this directory instead of in memory.
+    pub dir: Option<PathBuf>,
+    /// How the `callback_url` of the jobs are notified.
+    pub callbacks: CallbackSettings,
+}
+
+impl Default for JobSettings {
+    fn default() -> Self {
+        JobSettings {
+            workers: 2,
+            queue_size: 100,
+            ttl: Duration::from_secs(3600),
+            dir: None,
+            callbacks: CallbackSettings::default(),
+        }
+    }
+}
+
+impl JobSettings {
+    /// Overrides the settings with the environment variables.
+    pub fn with_env(self) -> Self {
+        JobSettings {
+            workers: env_or("JOBS_WORKERS", self.workers),
+            queue_size: env_or("JOBS_QUEUE_SIZE", self.queue_size),
+            ttl: Duration::from_secs(env_or("JOBS_TTL", self.ttl.as_secs())),
+            dir: std::env::var("JOBS_DIR")
+                .ok()
+                .map(PathBuf::from)
+                .or(self.dir),
+            callbacks: self.callbacks.with_env(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Finished,
+    Failed,
+}
+
+/// State of a validation job, as returned by `GET /jobs/{id}`.
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone)]
+pub struct Job {
+    pub id: String,
+    pub status: JobStatus,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Url notified when the job is finished.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub callback_url: Option<String>,
+}
+
+impl Job {
+    fn new() -> Self {
+        Job {
+            id: uuid::Uuid::new_v4().to_string(),
+            status: JobStatus::Queued,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            error: None,
+            callback_url: None,
+        }
+    }
+
+    fn is_expired(&self, ttl: Duration) -> bool {
+        self.finished_at
+            .map(|finished_at| {
+                Utc::now()
+                    .signed_duration_since(finished_at)
+                    .to_std()
+                    .map(|elapsed| elapsed > ttl)
+                    .unwrap_or(false)
+            })
+            .unwrap_or(false)
+    }
+}
+
+/// What is validated by a job.
+pub enum JobInput {
+    /// The GTFS is downloaded from this url.
+    Url(String),
+    /// The GTFS archive itself.
+    Archive(Vec<u8>),
+}
+
+struct Task {
+    id: String,
+    input: JobInput,
+    custom_rules: Arc<CustomRules>,
+    max_size: usize,
+    no_cache: bool,
+    callback: Option<Callback>,
+}
+
+/// Holds the jobs and their serialized [Response].
+///
+/// [Response]: ../../validate/struct.Response.html
+struct JobStore {
+    jobs: Mutex<HashMap<String, (Job, Option<String>)>>,
+    /// Steps of the validations, only kept in memory.
+    steps: Mutex<HashMap<String, Vec<Step>>>,
+    /// Incremented each time a job gets a new step or is finished.
+    changes: tokio::sync::watch::Sender<u64>,
+    /// Number of validations running, including the ones of the jobs failed after the
+    /// validation timeout, which cannot be interrupted.
+    validations: AtomicUsize,
+    ttl: Duration,
+    dir: Option<PathBuf>,
+}
+
+impl JobStore {
+    fn new(ttl: Duration, dir: Option<PathBuf>) -> Self {
+        let store = JobStore {
+            jobs: Mutex::new(HashMap::new()),
+            steps: Mutex::new(HashMap::new()),
+            changes: tokio::sync::watch::Sender::new(0),
+            validations: AtomicUsize::new(0),
+            ttl,
+            dir,
+        };
+        store.load();
+        store
+    }
+
+    // Reload the jobs stored in the directory by a previous run of the dæmon.
+    // Jobs that were not finished will never be, so they are marked as failed.
+    fn load(&self) {
+        let Some(dir) = &self.dir else { return };
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            log::error!("impossible to create the jobs directory {:?}: {}", dir, e);
+            return;
+        }
+        let entries = std::fs::read_dir(dir).into_iter().flatten().flatten();
+        let mut jobs = self.jobs.lock().unwrap();
+        for entry in entries.filter(|e| e.path().to_string_lossy().ends_with(".job.json")) {
+            let job = std::fs::read(entry.path())
+                .ok()
+                .and_then(|content| serde_json::from_slice::<Job>(&content).ok());
+            if let Some(mut job) = job {
+                if job.finished_at.is_none() {
+                    job.status = JobStatus::Failed;
+                    job.finished_at = Some(Utc::now());
+                    job.error = Some("The dæmon was restarted during the validation".to_owned());
+                    self.persist(&job, None);
+                }
+                jobs.insert(job.id.clone(), (job, None));
+            }
+        }
+    }
+
+    fn job_path(&self, id: &str) -> Option<PathBuf> {
+        self.dir
+            .as_ref()
+            .map(|d| d.join(format!("{}.job.json", id)))
+    }
+
+    fn result_path(&self, id: &str) -> Option<PathBuf> {
+        self.dir.as_ref().map(|d| d.join(format!("{}.json", id)))
+    }
+
+    // Write the job (and its result) in the directory, if any.
+    // Returns the result if it still needs to be kept in memory.
+    fn persist(&self, job: &Job, result: Option<String>) -> Option<String> {
+        let (Some(job_path), Some(result_path)) =
+            (self.job_path(&job.id), self.result_path(&job.id))
+        else {
+            return result;
+        };
+        let written = result
+            .as_ref()
+            .map_or(Ok(()), |r| std::fs::write(&result_path, r))
+            .and_then(|_| std::fs::write(&job_path, serde_json::to_vec(job)?));
+        match written {
+            Ok(()) => None,
+            Err(e) => {
+                log::error!("impossible to store the job {}: {}", job.id, e);
+                result
+            }
+        }
+    }
+
+    fn insert(&self, job: Job) {
+        self.persist(&job, None);
+        self.jobs
+            .lock()
+            .unwrap()
+            .insert(job.id.clone(), (job, None));
+    }
+
+    fn remove(&self, id: &str) {
+        self.jobs.lock().unwrap().remove(id);
+        self.steps.lock().unwrap().remove(id);
+        for path in self.job_path(id).into_iter().chain(self.result_path(id)) {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    fn push_step(&self, id: &str, step: Step) {
+        self.steps
+            .lock()
+            .unwrap()
+            .entry(id.to_owned())
+            .or_default()
+            .push(step);
+        self.changes.send_modify(|changes| *changes += 1);
+    }
+
+    /// The steps of the job, skipping the `from` first ones.
+    fn steps(&self, id: &str, from: usize) -> Vec<Step> {
+        self.steps
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|steps| steps.iter().skip(from).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn get(&self, id: &str) -> Option<Job> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|(job, _)| job.clone())
+    }
+
+    /// Number of jobs with this status.
+    fn count(&self, status: JobStatus) -> usize {
+        self.jobs
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|(job, _)| job.status == status)
+            .count()
+    }
+
+    fn start(&self, id: &str) {
+        if let Some((job, _)) = self.jobs.lock().unwrap().get_mut(id) {
+            job.status = JobStatus::Running;
+            job.started_at = Some(Utc::now());
+            self.persist(job, None);
+        }
+    }
+
+    fn finish(&self, id: &str, result: Result<String, String>) {
+        let Some(mut job) = self.get(id) else { return };
+        job.finished_at = Some(Utc::now());
+        let result = match result {
+            Ok(result) => {
+                job.status = JobStatus::Finished;
+                Some(result)
+            }
+            Err(e) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(e);
+                None
+            }
+        };
+        let in_memory = self.persist(&job, result);
+        self.jobs
+            .lock()
+            .unwrap()
+            .insert(id.to_owned(), (job, in_memory));
+        self.changes.send_modify(|changes| *changes += 1);
+    }
+
+    fn result(&self, id: &str) -> Option<String> {
+        let in_memory = self
+            .jobs
+            .lock()
+            .unwrap()
+            .get(id)
+            .and_then(|(_, result)| result.clone());
+        in_memory.or_else(|| {
+            self.result_path(id)
+                .and_then(|path| std::fs::read_to_string(path).ok())
+        })
+    }
+
+    fn purge_expired(&self) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let expired: Vec<String> = jobs
+            .values()
+            .filter(|(job, _)| job.is_expired(self.ttl))
+            .map(|(job, _)| job.id.clone())
+            .collect();
+        let mut steps = self.steps.lock().unwrap();
+        for id in expired {
+            jobs.remove(&id);
+            steps.remove(&id);
+            for path in self.job_path(&id).into_iter().chain(self.result_path(&id)) {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+/// Queue of validation jobs, consumed by a pool of worker threads.
+pub struct JobQueue {
+    store: Arc<JobStore>,
+    sender: SyncSender<Task>,
+    queue_size: usize,
+    limits: Arc<Limits>,
+    /// Set on shutdown, for the workers to stop taking new jobs.
+    stopping: Arc<AtomicBool>,
+}
+
+impl JobQueue {
+    pub fn new(
+        settings: JobSettings,
+        cache: Arc<ResultCache>,
+        metrics: Arc<Metrics>,
+        limits: Arc<Limits>,
+    ) -> Self {
+        let (sender, receiver) = sync_channel(settings.queue_size);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let store = Arc::new(JobStore::new(settings.ttl, settings.dir));
+        let callbacks = Arc::new(settings.callbacks);
+        let stopping = Arc::new(AtomicBool::new(false));
+        for _ in 0..settings.workers.max(1) {
+            let receiver = Arc::clone(&receiver);
+            let store = Arc::clone(&store);
+            let cache = Arc::clone(&cache);
+            let metrics = Arc::clone(&metrics);
+            let limits = Arc::clone(&limits);
+            let callbacks = Arc::clone(&callbacks);
+            let stopping = Arc::clone(&stopping);
+            std::thread::spawn(move || {
+                run_worker(
+                    &receiver, &store, &cache, &metrics, &limits, &callbacks, &stopping,
+                )
+            });
+        }
+        JobQueue {
+            store,
+            sender,
+            queue_size: settings.queue_size,
+            limits,
+            stopping,
+        }
+    }
+
+    /// Queues a validation. Returns `None` if the queue is full.
+    pub fn submit(
+        &self,
+        input: JobInput,
+        custom_rules: Arc<CustomRules>,
+        max_size: usize,
+        no_cache: bool,
+        callback: Option<Callback>,
+    ) -> Option<Job> {
+        self.store.purge_expired();
+        let mut job = Job::new();
+        job.callback_url = callback.as_ref().map(|c| c.url.clone());
+        self.store.insert(job.clone());
+        let task = Task {
+            id: job.id.clone(),
+            input,
+            custom_rules,
+            max_size,
+            no_cache,
+            callback,
+        };
+        match self.sender.try_send(task) {
+            Ok(()) => Some(job),
+            Err(_) => {
+                self.store.remove(&job.id);
+                None
+            }
+        }
+    }
+
+    /// Queues a validation requested by `req`, answering `202 Accepted` with the job.
+    ///
+    /// If a `callback_url` is given, it is notified when the job is finished.
+    pub fn enqueue(
+        &self,
+        req: &HttpRequest,
+        input: JobInput,
+        custom_rules: Arc<CustomRules>,
+        max_size: usize,
+        no_cache: bool,
+        callback_url: Option<&str>,
+    ) -> Result<HttpResponse, Error> {
+        let callback = match callback_url {
+            Some(url) => {
+                self.limits.check_url(url)?;
+                let info = req.connection_info();
+                Some(Callback {
+                    url: url.to_owned(),
+                    base_url: format!("{}://{}", info.scheme(), info.host()),
+                })
+            }
+            None => None,
+        };
+        match self.submit(input, custom_rules, max_size, no_cache, callback) {
+            Some(job) => Ok(HttpResponse::Accepted()
+                .insert_header((header::LOCATION, format!("/jobs/{}", job.id)))
+                .json(job)),
+            None => Err(error::ErrorServiceUnavailable(
+                "too many validations are waiting, retry later",
+            )),
+        }
+    }
+
+    /// True if no more job can be queued.
+    pub fn is_saturated(&self) -> bool {
+        self.store.count(JobStatus::Queued) >= self.queue_size
+    }
+
+    /// Stops starting new jobs, and waits at most `timeout` for the running validations to finish.
+    ///
+    /// The jobs still queued are lost, and marked as failed by the next run if stored in a
+    /// directory. The validations of the jobs that failed after the validation timeout are
+    /// waited for too, as they still run.
+    pub fn drain(&self, timeout: Duration) {
+        self.stopping.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + timeout;
+        loop {
+            let running = self.store.validations.load(Ordering::SeqCst);
+            if running == 0 {
+                return;
+            }
+            if Instant::now() >= deadline {
+                log::warn!(
+                    "{} validations are abandoned, still running at shutdown",
+                    running
+                );
+                return;
+            }
+            log::info!("Waiting for {} running validations", running);
+            std::thread::sleep(Duration::from_millis(500).min(timeout));
+        }
+    }
+
+    pub fn job(&self, id: &str) -> Option<Job> {
+        self.store.purge_expired();
+        self.store.get(id)
+    }
+
+    /// The steps of the validation of the job, skipping the `from` first ones.
+    pub fn steps(&self, id: &str, from: usize) -> Vec<Step> {
+        self.store.steps(id, from)
+    }
+
+    /// The serialized [Response] of a finished job.
+    ///
+    /// [Response]: ../../validate/struct.Response.html
+    pub fn result(&self, id: &str) -> Option<String> {
+        self.store.result(id)
+    }
+}
+
+fn run_worker(
+    receiver: &Mutex<Receiver<Task>>,
+    store: &JobStore,
+    cache: &ResultCache,
+    metrics: &Metrics,
+    limits: &Limits,
+    callbacks: &Arc<CallbackSettings>,
+    stopping: &AtomicBool,
+) {
+    loop {
+        let task = receiver.lock().unwrap().recv();
+        let Ok(mut task) = task else {
+            // the queue has been dropped, there won't be any more job
+            return;
+        };
+        if stopping.load(Ordering::SeqCst) {
+            return;
+        }
+        log::info!("Starting validation of job {}", task.id);
+        store.start(&task.id);
+        let id = task.id.clone();
+        let callback = task.callback.take();
+        store.validations.fetch_add(1, Ordering::SeqCst);
+        std::thread::scope(|scope| {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let job_id = id.as_str();
+            scope.spawn(move || {
+                let progress = |step| store.push_step(job_id, step);
+                let _ = sender.send(validate_input(task, cache, metrics, limits, &progress));
+            });
+            let result = receiver
+                .recv_timeout(limits.settings.validation_timeout)
+                .unwrap_or_else(|_| {
+                    log::warn!("The validation of job {} took too long", id);
+                    Err("the validation took too long and has been abandoned".to_owned())
+                });
+            store.finish(&id, result);
+            if let Some(callback) = callback
+                && let Some(job) = store.get(&id)
+            {
+                let result = store.result(&id);
+                let notification = Notification::new(job, &callback.base_url, result.as_deref());
+                callbacks::notify(callbacks, callback, notification);
+            }
+            // after the timeout, the job is failed but its validation cannot be interrupted,
+            // so the worker waits for it to actually finish before taking another job
+        });
+        store.validations.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn validate_input(
+    task: Task,
+    cache: &ResultCache,
+    metrics: &Metrics,
+    limits: &Limits,
+    progress: &impl Progress,
+) -> Result<String, String> {
+    let custom_rules = &task.custom_rules;
+    let archive = match task.input {
+        JobInput::Url(url) => {
+            let start = Instant::now();
+            let archive = progress::run(progress, Stage::Download, None, || {
+                limits.download_blocking(&url)
+            });
+            metrics.observe_download(archive.is_ok(), start.elapsed());
+            archive
+        }
+        JobInput::Archive(archive) => Ok(archive),
+    };
+    let json = match archive {
+        Ok(archive) => {
+            cache
+                .validate(
+                    &archive,
+                    task.max_size,
+                    custom_rules,
+                    task.no_cache,
+                    progress,
+                )
+                .json
+        }
+        Err(DownloadError::Fetch(e)) => {
+            let response = process(Err(e), task.max_size, custom_rules);
+            serde_json::to_vec(&response)
+                .map_err(|e| e.to_string())?
+                .into()
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    log::info!("Finished validation of job {}", task.id);
+    String::from_utf8(json.to_vec()).map_err(|e| e.to_string())
+}
+
+/// Interval of the comments keeping the event streams open.
+const KEEP_ALIVE: Duration = Duration::from_secs(20);
+
+#[derive(Deserialize)]
+struct JobParams {
+    url: Option<String>,
+    max_size: Option<usize>,
+    #[serde(default)]
+    no_cache: bool,
+    profile: Option<String>,
+    callback_url: Option<String>,
+}
+
+/// Queues a validation of the GTFS at `url`, or of the archive sent as body.
+#[post("/jobs")]
+async fn create_job(
+    req: HttpRequest,
+    queue: web::Data<JobQueue>,
+    params: web::Query<JobParams>,
+    payload: web::Payload,
+    profiles: web::Data<Profiles>,
+) -> Result<HttpResponse, Error> {
+    let custom_rules = profiles.get(params.profile.as_deref())?;
+    let body = read_payload(payload, queue.limits.settings.max_upload_size).await?;
+    let input = match &params.url {
+        Some(url) => {
+            queue.limits.check_url(url)?;
+            JobInput::Url(url.clone())
+        }
+        None if !body.is_empty() => JobInput::Archive(body.to_vec()),
+        None => {
+            return Err(error::ErrorBadRequest(
+                "a GTFS url parameter or a GTFS archive as body is needed",
+            ));
+        }
+    };
+    queue.enqueue(
+        &req,
+        input,
+        custom_rules,
+        params.max_size.unwrap_or(1000),
+        params.no_cache,
+        params.callback_url.as_deref(),
+    )
+}
+
+#[get("/jobs/{id}")]
+async fn job_status(
+    queue: web::Data<JobQueue>,
+    id: web::Path<String>,
+) -> Result<web::Json<Job>, Error> {
+    queue
+        .job(&id)
+        .map(web::Json)
+        .ok_or_else(|| error::ErrorNotFound("unknown or expired job"))
+}
+
+/// Returns the [Response] of a finished job, or its state if it is not finished yet or has failed.
+///
+/// [Response]: ../../validate/struct.Response.html
+#[get("/jobs/{id}/result")]
+async fn job_result(
+    queue: web::Data<JobQueue>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let job = queue
+        .job(&id)
+        .ok_or_else(|| error::ErrorNotFound("unknown or expired job"))?;
+    match job.status {
+        JobStatus::Queued | JobStatus::Running => Ok(HttpResponse::Accepted().json(job)),
+        // a failed job is not a fault of the dæmon, the job tells why it has failed
+        JobStatus::Failed => Ok(HttpResponse::UnprocessableEntity().json(job)),
+        JobStatus::Finished => {
+            let id = id.into_inner();
+            let result = web::block(move || queue.result(&id)).await?;
+            result
+                .map(|r| HttpResponse::Ok().content_type(ContentType::json()).body(r))
+                .ok_or_else(|| error::ErrorNotFound("the result of the job is not available"))
+        }
+    }
+}
+
+/// Streams the steps of the validation of a job as Server-Sent Events.
+///
+/// Each step is sent as a `step` event when it starts and when it is finished. The stream
+/// ends with a `finished` or `failed` event holding the job.
+#[get("/jobs/{id}/events")]
+async fn job_events(
+    queue: web::Data<JobQueue>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let id = id.into_inner();
+    queue
+        .job(&id)
+        .ok_or_else(|| error::ErrorNotFound("unknown or expired job"))?;
+    let changes = queue.store.changes.subscribe();
+    // the state of the stream is the number of steps already sent, None once it is over
+    let events = futures::stream::unfold(Some(0), move |sent| {
+        let queue = queue.clone();
+        let id = id.clone();
+        let mut changes = changes.clone();
+        async move {
+            let sent = sent?;
+            loop {
+                changes.mark_unchanged();
+                // the job is read before its steps, so all the steps of a finished job are sent
+                let job = queue.store.get(&id)?;
+                let steps = queue.steps(&id, sent);
+                if !steps.is_empty() {
+                    let events: String = steps.iter().map(|s| sse_event("step", s)).collect();
+                    return Some((Ok::<_, Error>(events.into()), Some(sent + steps.len())));
+                }
+                match job.status {
+                    JobStatus::Finished => {
+                        return Some((Ok(sse_event("finished", &job).into()), None));
+                    }
+                    JobStatus::Failed => return Some((Ok(sse_event("failed", &job).into()), None)),
+                    JobStatus::Queued | JobStatus::Running => {}
+                }
+                // waits for a new step, or for the end of the job
+                match actix_rt::time::timeout(KEEP_ALIVE, changes.changed()).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(_)) => return None,
+                    // a comment, to keep the connection open during long steps
+                    Err(_) => {
+                        return Some((Ok(web::Bytes::from(": keep-alive\n\n")), Some(sent)));
+                    }
+                }
+            }
+        }
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+        .streaming(events))
+}
+
+fn sse_event(name: &str, data: &impl Serialize) -> String {
+    format!(
+        "event: {}\ndata: {}\n\n",
+        name,
+        serde_json::to_string(data).unwrap_or_default()
+    )
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(create_job)
+        .service(job_status)
+        .service(job_result)
+        .service(job_events);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::tests::{limits, zip_directory};
+    use actix_web::App;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{
+        TestRequest, call_and_read_body, call_and_read_body_json, call_service, init_service,
+        read_body_json,
+    };
+
+    fn job_queue(dir: Option<PathBuf>) -> JobQueue {
+        let metrics = Arc::new(Metrics::new());
+        let cache = ResultCache::new(
+            crate::daemon::cache::CacheSettings {
+                size: 0,
+                dir: None,
+                dir_size: 0,
+                max_age: 0,
+            },
+            metrics.clone(),
+        );
+        JobQueue::new(settings(dir), Arc::new(cache), metrics, Arc::new(limits()))
+    }
+
+    fn settings(dir: Option<PathBuf>) -> JobSettings {
+        JobSettings {
+            workers: 1,
+            queue_size: 10,
+            ttl: Duration::from_secs(60),
+            dir,
+            callbacks: CallbackSettings {
+                secret: Some("secret".to_owned()),
+                retries: 0,
+                backoff: Duration::ZERO,
+                timeout: Duration::from_secs(5),
+            },
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_job_lifecycle() {
+        let queue = web::Data::new(job_queue(None));
+        let app = init_service(
+            App::new()
+                .app_data(queue.clone())
+                .app_data(web::Data::new(Profiles::default()))
+                .configure(configure),
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/jobs")
+            .set_payload(zip_directory("test_data/unused_stop"))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(StatusCode::ACCEPTED, resp.status());
+        let job: Job = read_body_json(resp).await;
+
+        let mut status = job.status;
+        for _ in 0..100 {
+            let req = TestRequest::get()
+                .uri(&format!("/jobs/{}", job.id))
+                .to_request();
+            let job: Job = call_and_read_body_json(&app, req).await;
+            status = job.status;
+            if status == JobStatus::Finished {
+                break;
+            }
+            actix_rt::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(JobStatus::Finished, status);
+
+        let req = TestRequest::get()
+            .uri(&format!("/jobs/{}/result", job.id))
+            .to_request();
+        let result: serde_json::Value = call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            1,
+            result["validations"]["UnusedStop"]
+                .as_array()
+                .unwrap()
+                .len()
+        );
+
+        // a failed job has no result, it tells why
+        let failed = Job::new();
+        queue.store.insert(failed.clone());
+        queue
+            .store
+            .finish(&failed.id, Err("the GTFS is broken".to_owned()));
+        let req = TestRequest::get()
+            .uri(&format!("/jobs/{}/result", failed.id))
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, resp.status());
+        let failed: Job = read_body_json(resp).await;
+        assert_eq!(Some("the GTFS is broken".to_owned()), failed.error);
+    }
+
+    #[actix_web::test]
+    async fn test_events() {
+        let queue = web::Data::new(job_queue(None));
+        let app = init_service(
+            App::new()
+                .app_data(queue)
+                .app_data(web::Data::new(Profiles::default()))
+                .configure(configure),
+        )
+        .await;
+
+        let req = TestRequest::post()
+            .uri("/jobs")
+            .set_payload(zip_directory("test_data/unused_stop"))
+            .to_request();
+        let job: Job = call_and_read_body_json(&app, req).await;
+
+        // the stream ends when the job is finished
+        let req = TestRequest::get()
+            .uri(&format!("/jobs/{}/events", job.id))
+            .to_request();
+        let body = call_and_read_body(&app, req).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.starts_with("event: step\ndata: {\"stage\":\"parsing\"}\n\n"));
+        assert!(
+            body.contains(
+                "data: {\"stage\":\"validation\",\"name\":\"unused_stop\",\"duration_ms\":"
+            )
+        );
+        assert!(body.contains("{\"stage\":\"model_building\",\"duration_ms\":"));
+        assert!(body.ends_with("\n\n"));
+        let last = body.trim_end().rsplit("\n\n").next().unwrap();
+        assert!(last.starts_with("event: finished\ndata: {"));
+
+        let req = TestRequest::get().uri("/jobs/unknown/events").to_request();
+        assert_eq!(
+            StatusCode::NOT_FOUND,
+            call_service(&app, req).await.status()
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_callback() {
+        let queue = web::Data::new(job_queue(None));
+        let app = init_service(
+            App::new()
+                .app_data(queue)
+                .app_data(web::Data::new(Profiles::default()))
+                .configure(configure),
+        )
+        .await;
+        let (callback_url, received) = crate::daemon::callbacks::tests::stand_in(vec![]);
+
+        let req = TestRequest::post()
+            .uri(&format!("/jobs?max_size=1&callback_url={}", callback_url))
+            .set_payload(zip_directory("test_data/unused_stop"))
+            .to_request();
+        let job: Job = call_and_read_body_json(&app, req).await;
+        assert_eq!(Some(callback_url), job.callback_url);
+
+        let notification = received.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(
+            Some(callbacks::sign("secret", &notification.body).as_str()),
+            notification.header(callbacks::SIGNATURE_HEADER)
+        );
+        let notification: serde_json::Value = serde_json::from_slice(&notification.body).unwrap();
+        assert_eq!("finished", notification["job"]["status"]);
+        assert_eq!(1, notification["issues_count"]["UnusedStop"]);
+        // all the issues are counted, not only the max_size ones in the result
+        assert_eq!(3, notification["issues_count"]["InvalidReference"]);
+        assert!(
+            notification["result_url"]
+                .as_str()
+                .unwrap()
+                .ends_with(&format!("/jobs/{}/result", job.id))
+        );
+
+        let req = TestRequest::post()
+            .uri("/jobs?url=https://example.org/gtfs.zip&callback_url=http://localhost/")
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(StatusCode::FORBIDDEN, resp.status());
+    }
+
+    #[test]
+    fn test_drain() {
+        let queue = job_queue(None);
+        let archive = zip_directory("test_data/unused_stop");
+        let job = queue
+            .submit(
+                JobInput::Archive(archive),
+                Arc::default(),
+                1000,
+                false,
+                None,
+            )
+            .unwrap();
+        // wait for the job to start, the drain then waits for its end
+        for _ in 0..100 {
+            if queue.job(&job.id).unwrap().status != JobStatus::Queued {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        queue.drain(Duration::from_secs(10));
+        assert_eq!(JobStatus::Finished, queue.job(&job.id).unwrap().status);
+    }
+
+    #[test]
+    fn test_validation_timeout() {
+        let mut limits = limits();
+        limits.settings.validation_timeout = Duration::ZERO;
+        let metrics = Arc::new(Metrics::new());
+        let cache = ResultCache::new(
+            crate::daemon::cache::CacheSettings {
+                size: 0,
+                dir: None,
+                dir_size: 0,
+                max_age: 0,
+            },
+            metrics.clone(),
+        );
+        let queue = JobQueue::new(settings(None), Arc::new(cache), metrics, Arc::new(limits));
+        let job = queue
+            .submit(
+                JobInput::Archive(zip_directory("test_data/unused_stop")),
+                Arc::default(),
+                1000,
+                false,
+                None,
+            )
+            .unwrap();
+        for _ in 0..100 {
+            if queue.job(&job.id).unwrap().finished_at.is_some() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let job = queue.job(&job.id).unwrap();
+        assert_eq!(JobStatus::Failed, job.status);
+        assert_eq!(
+            Some("the validation took too long and has been abandoned".to_owned()),
+            job.error
+        );
+        // the abandoned validation still runs, the drain waits for it
+        queue.drain(Duration::from_secs(10));
+        assert_eq!(0, queue.store.validations.load(Ordering::SeqCst));
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_job() {
+        let queue = web::Data::new(job_queue(None));
+        let app = init_service(
+            App::new()
+                .app_data(queue)
+                .app_data(web::Data::new(Profiles::default()))
+                .configure(configure),
+        )
+        .await;
+
+        let req = TestRequest::get().uri("/jobs/unknown").to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(StatusCode::NOT_FOUND, resp.status());
+
+        let req = TestRequest::post().uri("/jobs").to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+    }
+
+    #[test]
+    fn test_expiry() {
+        let store = JobStore::new(Duration::from_secs(60), None);
+        let mut job = Job::new();
+        let id = job.id.clone();
+        store.insert(job.clone());
+        store.finish(&id, Ok("{}".to_owned()));
+        store.purge_expired();
+        assert_eq!(Some("{}".to_owned()), store.result(&id));
+
+        job.status = JobStatus::Finished;
+        job.finished_at = Some(Utc::now() - chrono::Duration::minutes(2));
+        store.jobs.lock().unwrap().insert(id.clone(), (job, None));
+        store.purge_expired();
+        assert!(store.get(&id).is_none());
+    }
+
+    #[test]
+    fn test_on_disk_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = JobStore::new(Duration::from_secs(60), Some(dir.path().to_owned()));
+        let finished = Job::new();
+        let queued = Job::new();
+        let running = Job::new();
+        store.insert(finished.clone());
+        store.finish(&finished.id, Ok("{}".to_owned()));
+        store.insert(queued.clone());
+        store.insert(running.clone());
+        store.start(&running.id);
+
+        // a new store, like after a restart of the dæmon, finds back the jobs
+        let store = JobStore::new(Duration::from_secs(60), Some(dir.path().to_owned()));
+        assert_eq!(
+            Some(JobStatus::Finished),
+            store.get(&finished.id).map(|j| j.status)
+        );
+        assert_eq!(Some("{}".to_owned()), store.result(&finished.id));
+        // the unfinished jobs will never be, they failed
+        for id in [&queued.id, &running.id] {
+            assert_eq!(Some(JobStatus::Failed), store.get(id).map(|j| j.status));
+        }
+    }
+}
@@ -0,0 +1,442 @@
+//! Limits protecting the dæmon from too large, too long or too many validations.
+use super::config::seconds;
+use super::env_or;
+use actix_web::{Error, error, web};
+use serde::Deserialize;
+use std::fmt;
+use std::io::Read;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Maximum number of redirections followed when downloading a GTFS.
+const MAX_REDIRECTS: usize = 10;
+
+/// Settings of the limits.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitSettings {
+    /// Maximum size in bytes of a GTFS sent as body.
+    pub max_upload_size: usize,
+    /// Maximum size in bytes of a downloaded GTFS.
+    pub max_download_size: usize,
+    #[serde(deserialize_with = "seconds")]
+    pub download_timeout: Duration,
+    /// Maximum duration of a validation, synchronous or queued as a job.
+    #[serde(deserialize_with = "seconds")]
+    pub validation_timeout: Duration,
+    /// Maximum number of synchronous validations running at the same time.
+    pub max_concurrent_validations: usize,
+    /// If not empty, GTFS can only be downloaded from these hosts (or their subdomains).
+    pub allowed_hosts: Vec<String>,
+    /// GTFS can never be downloaded from these hosts (or their subdomains).
+    pub denied_hosts: Vec<String>,
+}
+
+impl Default for LimitSettings {
+    fn default() -> Self {
+        LimitSettings {
+            max_upload_size: 500_000_000,
+            max_download_size: 500_000_000,
+            download_timeout: Duration::from_secs(120),
+            validation_timeout: Duration::from_secs(600),
+            max_concurrent_validations: std::thread::available_parallelism().map_or(4, |n| n.get()),
+            allowed_hosts: Vec::new(),
+            denied_hosts: Vec::new(),
+        }
+    }
+}
+
+impl LimitSettings {
+    /// Overrides the settings with the environment variables.
+    pub fn with_env(self) -> Self {
+        let hosts = |name, hosts: Vec<String>| -> Vec<String> {
+            match std::env::var(name) {
+                Ok(list) => list.split(',').map(str::to_owned).collect(),
+                Err(_) => hosts,
+            }
+            .into_iter()
+            .map(|h| h.trim().to_lowercase())
+            .filter(|h| !h.is_empty())
+            .collect()
+        };
+        let seconds =
+            |name, default: Duration| Duration::from_secs(env_or(name, default.as_secs()));
+        LimitSettings {
+            max_upload_size: env_or("MAX_UPLOAD_SIZE", self.max_upload_size),
+            max_download_size: env_or("MAX_DOWNLOAD_SIZE", self.max_download_size),
+            download_timeout: seconds("DOWNLOAD_TIMEOUT", self.download_timeout),
+            validation_timeout: seconds("VALIDATION_TIMEOUT", self.validation_timeout),
+            max_concurrent_validations: env_or(
+                "MAX_CONCURRENT_VALIDATIONS",
+                self.max_concurrent_validations,
+            ),
+            allowed_hosts: hosts("ALLOWED_HOSTS", self.allowed_hosts),
+            denied_hosts: hosts("DENIED_HOSTS", self.denied_hosts),
+        }
+    }
+}
+
+/// Why a GTFS could not be downloaded.
+#[derive(Debug)]
+pub enum DownloadError {
+    /// The url is invalid, or its host is not allowed.
+    Forbidden(String),
+    /// The file is larger than the maximum download size.
+    TooLarge(usize),
+    /// The file could not be fetched.
+    Fetch(gtfs_structures::Error),
+}
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DownloadError::Forbidden(reason) => write!(f, "{}", reason),
+            DownloadError::TooLarge(max) => {
+                write!(
+                    f,
+                    "the GTFS is larger than the maximum size of {} bytes",
+                    max
+                )
+            }
+            DownloadError::Fetch(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for DownloadError {
+    fn from(e: reqwest::Error) -> Self {
+        // a redirection is refused when its target url is not allowed
+        match std::error::Error::source(&e) {
+            Some(reason) if e.is_redirect() => DownloadError::Forbidden(reason.to_string()),
+            _ => DownloadError::Fetch(e.into()),
+        }
+    }
+}
+
+impl From<std::io::Error> for DownloadError {
+    fn from(e: std::io::Error) -> Self {
+        DownloadError::Fetch(e.into())
+    }
+}
+
+impl From<DownloadError> for Error {
+    fn from(e: DownloadError) -> Self {
+        match e {
+            DownloadError::Forbidden(_) => error::ErrorForbidden(e.to_string()),
+            DownloadError::TooLarge(_) => error::ErrorPayloadTooLarge(e.to_string()),
+            DownloadError::Fetch(_) => error::ErrorBadGateway(e.to_string()),
+        }
+    }
+}
+
+pub struct Limits {
+    pub settings: LimitSettings,
+    running: Arc<AtomicUsize>,
+    client: reqwest::Client,
+}
+
+/// A slot among the concurrent validations, released when dropped.
+pub struct ValidationPermit(Arc<AtomicUsize>);
+
+impl Drop for ValidationPermit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Checks that a GTFS can be downloaded from this url, given the allowed and denied hosts.
+fn check_host(allowed: &[String], denied: &[String], url: &url::Url) -> Result<(), DownloadError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(DownloadError::Forbidden(format!(
+            "the {} scheme is not allowed",
+            url.scheme()
+        )));
+    }
+    let host = url.host_str().unwrap_or_default().to_lowercase();
+    let matches = |pattern: &String| {
+        host == *pattern
+            || host
+                .strip_suffix(pattern.as_str())
+                .is_some_and(|h| h.ends_with('.'))
+    };
+    if !(allowed.is_empty() || allowed.iter().any(matches)) || denied.iter().any(matches) {
+        return Err(DownloadError::Forbidden(format!(
+            "downloads from {} are not allowed",
+            host
+        )));
+    }
+    Ok(())
+}
+
+/// Follows the redirections only to the hosts a GTFS can be downloaded from.
+fn redirect_policy(settings: &LimitSettings) -> reqwest::redirect::Policy {
+    let (allowed, denied) = (
+        settings.allowed_hosts.clone(),
+        settings.denied_hosts.clone(),
+    );
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            attempt.error("too many redirections")
+        } else if let Err(e) = check_host(&allowed, &denied, attempt.url()) {
+            attempt.error(e.to_string())
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+impl Limits {
+    pub fn new(settings: LimitSettings) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(settings.download_timeout)
+            .redirect(redirect_policy(&settings))
+            .build()
+            .unwrap_or_default();
+        Limits {
+            settings,
+            running: Arc::new(AtomicUsize::new(0)),
+            client,
+        }
+    }
+
+    /// Checks that a GTFS can be downloaded from this url.
+    pub fn check_url(&self, url: &str) -> Result<(), DownloadError> {
+        let url = url::Url::parse(url)
+            .map_err(|e| DownloadError::Forbidden(format!("invalid url: {}", e)))?;
+        check_host(
+            &self.settings.allowed_hosts,
+            &self.settings.denied_hosts,
+            &url,
+        )
+    }
+
+    pub async fn download(&self, url: &str) -> Result<web::Bytes, DownloadError> {
+        self.check_url(url)?;
+        let max = self.settings.max_download_size;
+        let mut response = self.client.get(url).send().await?.error_for_status()?;
+        if response.content_length().is_some_and(|l| l as usize > max) {
+            return Err(DownloadError::TooLarge(max));
+        }
+        let mut body = web::BytesMut::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > max {
+                return Err(DownloadError::TooLarge(max));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body.freeze())
+    }
+
+    /// Downloads the GTFS, from a thread outside of the actix runtime.
+    pub fn download_blocking(&self, url: &str) -> Result<Vec<u8>, DownloadError> {
+        self.check_url(url)?;
+        let max = self.settings.max_download_size;
+        let response = reqwest::blocking::Client::builder()
+            .timeout(self.settings.download_timeout)
+            .redirect(redirect_policy(&self.settings))
+            .build()?
+            .get(url)
+            .send()?
+            .error_for_status()?;
+        let mut body = Vec::new();
+        response.take(max as u64 + 1).read_to_end(&mut body)?;
+        if body.len() > max {
+            return Err(DownloadError::TooLarge(max));
+        }
+        Ok(body)
+    }
+
+    /// Reserves a slot for a validation, or returns `None` if too many are running.
+    pub fn try_acquire(&self) -> Option<ValidationPermit> {
+        self.running
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |running| {
+                (running < self.settings.max_concurrent_validations).then_some(running + 1)
+            })
+            .ok()
+            .map(|_| ValidationPermit(Arc::clone(&self.running)))
+    }
+
+    /// True if no more synchronous validation can be run.
+    pub fn is_saturated(&self) -> bool {
+        self.running.load(Ordering::SeqCst) >= self.settings.max_concurrent_validations
+    }
+
+    /// Runs a validation on the blocking thread pool, failing after the validation timeout.
+    ///
+    /// The validation cannot be interrupted, so the permit is only released once it is
+    /// actually finished.
+    pub async fn run<F, R>(&self, permit: ValidationPermit, f: F) -> Result<R, Error>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let validation = web::block(move || {
+            let _permit = permit;
+            f()
+        });
+        match actix_rt::time::timeout(self.settings.validation_timeout, validation).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(error::ErrorGatewayTimeout(
+                "the validation took too long and has been abandoned",
+            )),
+        }
+    }
+
+    pub fn saturated_error() -> Error {
+        error::ErrorServiceUnavailable("too many validations are running, retry later")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::tests::{limits, zip_directory};
+    use actix_web::App;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{TestRequest, call_service, init_service};
+
+    #[test]
+    fn test_hosts() {
+        let mut limits = limits();
+        assert!(
+            limits
+                .check_url("https://transport.data.gouv.fr/gtfs.zip")
+                .is_ok()
+        );
+        assert!(limits.check_url("http://localhost:8080/gtfs.zip").is_err());
+        assert!(
+            limits
+                .check_url("https://data.example.com/gtfs.zip")
+                .is_err()
+        );
+        assert!(limits.check_url("https://notexample.com/gtfs.zip").is_ok());
+        assert!(limits.check_url("file:///etc/passwd").is_err());
+        assert!(limits.check_url("not an url").is_err());
+
+        limits.settings.allowed_hosts = vec!["data.gouv.fr".to_owned()];
+        assert!(
+            limits
+                .check_url("https://transport.data.gouv.fr/gtfs.zip")
+                .is_ok()
+        );
+        assert!(limits.check_url("https://notexample.com/gtfs.zip").is_err());
+    }
+
+    // Answers every connection with a redirection to `location`
+    fn redirect_server(location: &'static str) -> String {
+        use std::io::Write;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/gtfs.zip", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\n\r\n",
+                    location
+                );
+            }
+        });
+        url
+    }
+
+    #[actix_web::test]
+    async fn test_redirect_to_denied_host() {
+        let limits = Limits::new(LimitSettings {
+            allowed_hosts: vec!["127.0.0.1".to_owned()],
+            denied_hosts: vec!["localhost".to_owned()],
+            ..Default::default()
+        });
+        let url = redirect_server("http://localhost/latest/meta-data/");
+
+        assert!(limits.check_url(&url).is_ok());
+        let error = limits.download(&url).await.unwrap_err();
+        assert!(matches!(error, DownloadError::Forbidden(_)));
+        assert_eq!(
+            "downloads from localhost are not allowed",
+            error.to_string()
+        );
+
+        let limits = Arc::new(limits);
+        let blocking = Arc::clone(&limits);
+        let error = web::block(move || blocking.download_blocking(&url))
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(error, DownloadError::Forbidden(_)));
+    }
+
+    #[test]
+    fn test_concurrency() {
+        let limits = limits();
+        let first = limits.try_acquire();
+        let second = limits.try_acquire();
+        assert!(first.is_some() && second.is_some());
+        assert!(limits.try_acquire().is_none());
+        drop(first);
+        assert!(limits.try_acquire().is_some());
+    }
+
+    #[actix_web::test]
+    async fn test_requests() {
+        let limits = web::Data::new(limits());
+        let cache = web::Data::new(crate::daemon::cache::ResultCache::new(
+            crate::daemon::cache::CacheSettings {
+                size: 0,
+                dir: None,
+                dir_size: 0,
+                max_age: 0,
+            },
+            Arc::default(),
+        ));
+        let app = init_service(
+            App::new()
+                .app_data(limits.clone())
+                .app_data(cache)
+                .app_data(web::Data::new(crate::daemon::profiles::Profiles::default()))
+                .app_data(web::Data::new(crate::daemon::metrics::Metrics::new()))
+                .service(crate::daemon::validate)
+                .service(crate::daemon::validate_post),
+        )
+        .await;
+
+        let req = TestRequest::get()
+            .uri("/validate?url=http://localhost/gtfs.zip")
+            .to_request();
+        assert_eq!(
+            StatusCode::FORBIDDEN,
+            call_service(&app, req).await.status()
+        );
+
+        let req = TestRequest::post()
+            .uri("/validate")
+            .set_payload(vec![0; 200_000])
+            .to_request();
+        assert_eq!(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            call_service(&app, req).await.status()
+        );
+
+        let _permits = (limits.try_acquire(), limits.try_acquire());
+        let req = TestRequest::post()
+            .uri("/validate")
+            .set_payload(zip_directory("test_data/unused_stop"))
+            .to_request();
+        assert_eq!(
+            StatusCode::SERVICE_UNAVAILABLE,
+            call_service(&app, req).await.status()
+        );
+
+        // the upload is not read by a saturated dæmon
+        let req = TestRequest::post()
+            .uri("/validate")
+            .set_payload(vec![0; 200_000])
+            .to_request();
+        assert_eq!(
+            StatusCode::SERVICE_UNAVAILABLE,
+            call_service(&app, req).await.status()
+        );
+    }
+}
@@ -0,0 +1,259 @@
+//! Prometheus metrics of the dæmon, exposed on `GET /metrics`.
+use super::cache::CacheStatus;
+use crate::issues::Severity;
+use crate::validate::Response;
+use actix_web::{HttpResponse, get, web};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder, exponential_buckets,
+};
+use std::time::Duration;
+
+pub struct Metrics {
+    registry: Registry,
+    /// Number of validations, by outcome (`success`, `fatal` or `download_error`).
+    validations: IntCounterVec,
+    validation_duration: Histogram,
+    download_duration: HistogramVec,
+    feed_size: Histogram,
+    /// Number of issues found, by issue type.
+    issues: IntCounterVec,
+    /// Number of HTTP requests being handled.
+    in_flight: IntGauge,
+    /// Number of lookups in the results cache, by status (`hit`, `miss` or `bypass`).
+    cache: IntCounterVec,
+    /// Number of requests made with an API key, by key name and outcome.
+    api_requests: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let validations = IntCounterVec::new(
+            Opts::new(
+                "validator_validations_total",
+                "Number of validations, by outcome",
+            ),
+            &["outcome"],
+        )
+        .unwrap();
+        let validation_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "validator_validation_duration_seconds",
+                "Duration of the validations",
+            )
+            .buckets(exponential_buckets(0.05, 2., 12).unwrap()),
+        )
+        .unwrap();
+        let download_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "validator_download_duration_seconds",
+                "Duration of the downloads of the GTFS, by outcome",
+            )
+            .buckets(exponential_buckets(0.05, 2., 12).unwrap()),
+            &["outcome"],
+        )
+        .unwrap();
+        let feed_size = Histogram::with_opts(
+            HistogramOpts::new(
+                "validator_feed_size_bytes",
+                "Size of the validated GTFS archives",
+            )
+            .buckets(exponential_buckets(16_384., 4., 10).unwrap()),
+        )
+        .unwrap();
+        let issues = IntCounterVec::new(
+            Opts::new(
+                "validator_issues_total",
+                "Number of issues found, by issue type",
+            ),
+            &["issue_type"],
+        )
+        .unwrap();
+        let in_flight = IntGauge::new(
+            "validator_requests_in_flight",
+            "Number of HTTP requests being handled",
+        )
+        .unwrap();
+        let cache = IntCounterVec::new(
+            Opts::new(
+                "validator_cache_requests_total",
+                "Number of lookups in the results cache, by status",
+            ),
+            &["status"],
+        )
+        .unwrap();
+
+        let api_requests = IntCounterVec::new(
+            Opts::new(
+                "validator_api_requests_total",
+                "Number of requests made with an API key, by key and outcome",
+            ),
+            &["key", "outcome"],
+        )
+        .unwrap();
+
+        let registry = Registry::new();
+        registry.register(Box::new(validations.clone())).unwrap();
+        registry
+            .register(Box::new(validation_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(download_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(feed_size.clone())).unwrap();
+        registry.register(Box::new(issues.clone())).unwrap();
+        registry.register(Box::new(in_flight.clone())).unwrap();
+        registry.register(Box::new(cache.clone())).unwrap();
+        registry.register(Box::new(api_requests.clone())).unwrap();
+
+        Metrics {
+            registry,
+            validations,
+            validation_duration,
+            download_duration,
+            feed_size,
+            issues,
+            in_flight,
+            cache,
+            api_requests,
+        }
+    }
+
+    /// Records a validation that has been run on an archive of `size` bytes.
+    pub fn observe_validation(&self, response: &Response, size: usize, duration: Duration) {
+        let fatal = response
+            .validations
+            .values()
+            .flatten()
+            .any(|issue| issue.severity == Severity::Fatal);
+        self.validations
+            .with_label_values(&[if fatal { "fatal" } else { "success" }])
+            .inc();
+        self.validation_duration.observe(duration.as_secs_f64());
+        self.feed_size.observe(size as f64);
+        // the validations are truncated to max_size, the metadata counts all the issues
+        let issues_count = response.metadata.iter().flat_map(|m| &m.issues_count);
+        for (issue_type, count) in issues_count {
+            self.issues
+                .with_label_values(&[format!("{:?}", issue_type)])
+                .inc_by(*count as u64);
+        }
+    }
+
+    pub fn observe_download(&self, success: bool, duration: Duration) {
+        let outcome = if success { "success" } else { "error" };
+        self.download_duration
+            .with_label_values(&[outcome])
+            .observe(duration.as_secs_f64());
+        if !success {
+            self.validations
+                .with_label_values(&["download_error"])
+                .inc();
+        }
+    }
+
+    pub fn observe_cache(&self, status: CacheStatus) {
+        self.cache
+            .with_label_values(&[status.as_str().to_ascii_lowercase()])
+            .inc();
+    }
+
+    /// Records a request checked against the API keys, `key` being the name of its key.
+    pub fn observe_api_request(&self, key: &str, outcome: &str) {
+        self.api_requests.with_label_values(&[key, outcome]).inc();
+    }
+
+    /// Counts a HTTP request as in flight until the returned guard is dropped.
+    pub fn track_request(&self) -> InFlightRequest {
+        self.in_flight.inc();
+        InFlightRequest(self.in_flight.clone())
+    }
+
+    /// The metrics in the Prometheus text format.
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap_or_else(|e| log::error!("impossible to encode the metrics: {}", e));
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct InFlightRequest(IntGauge);
+
+impl Drop for InFlightRequest {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+#[get("/metrics")]
+pub async fn export(metrics: web::Data<Metrics>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(metrics.encode())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::cache::{CacheSettings, ResultCache};
+    use crate::daemon::tests::{limits, zip_directory};
+    use actix_web::App;
+    use actix_web::test::{TestRequest, call_and_read_body, call_service, init_service};
+
+    #[actix_web::test]
+    async fn test_metrics() {
+        let metrics = web::Data::new(Metrics::new());
+        let cache = web::Data::new(ResultCache::new(
+            CacheSettings {
+                size: 10,
+                dir: None,
+                dir_size: 0,
+                max_age: 0,
+            },
+            metrics.clone().into_inner(),
+        ));
+        let app = init_service(
+            App::new()
+                .app_data(metrics.clone())
+                .app_data(cache)
+                .app_data(web::Data::new(crate::daemon::profiles::Profiles::default()))
+                .app_data(web::Data::new(limits()))
+                .service(crate::daemon::validate_post)
+                .service(export),
+        )
+        .await;
+
+        let archive = zip_directory("test_data/agency_multiple");
+        for _ in 0..2 {
+            let req = TestRequest::post()
+                .uri("/validate?max_size=1")
+                .set_payload(archive.clone())
+                .to_request();
+            assert!(call_service(&app, req).await.status().is_success());
+        }
+        let req = TestRequest::post()
+            .uri("/validate")
+            .set_payload("not a zip")
+            .to_request();
+        call_service(&app, req).await;
+
+        let req = TestRequest::get().uri("/metrics").to_request();
+        let body = call_and_read_body(&app, req).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.contains("validator_validations_total{outcome=\"success\"} 1"));
+        assert!(body.contains("validator_validations_total{outcome=\"fatal\"} 1"));
+        assert!(body.contains("validator_cache_requests_total{status=\"hit\"} 1"));
+        assert!(body.contains("validator_cache_requests_total{status=\"miss\"} 2"));
+        // all the issues are counted, not only the max_size ones in the result
+        assert!(body.contains("validator_issues_total{issue_type=\"NegativeTravelTime\"} 2"));
+        assert!(body.contains("validator_feed_size_bytes_count 2"));
+    }
+}
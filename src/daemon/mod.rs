@@ -0,0 +1,365 @@
+use crate::custom_rules;
+use crate::validate::process;
+use actix_web::dev::Service;
+use actix_web::{
+    App, Error, HttpMessage, HttpRequest, HttpResponse, HttpServer, error, get, post, web,
+};
+use anyhow::Context;
+use futures::{FutureExt, Stream, StreamExt};
+use serde::Deserialize;
+use std::env;
+use std::sync::Arc;
+use std::time::Instant;
+
+mod auth;
+mod cache;
+mod callbacks;
+mod config;
+mod jobs;
+mod limits;
+mod metrics;
+mod openapi;
+mod profiles;
+mod report;
+mod status;
+mod ui;
+
+pub use config::Config;
+
+/// Parameters of a validation, from the query string or from the fields of a form.
+#[derive(Deserialize, Default)]
+struct Params {
+    url: Option<String>,
+    max_size: Option<usize>,
+    #[serde(default)]
+    no_cache: bool,
+    profile: Option<String>,
+    #[serde(default)]
+    format: report::OutputFormat,
+    /// If set, the validation is run as a job, and this url is notified when it is finished.
+    callback_url: Option<String>,
+}
+
+#[get("/validate")]
+async fn validate(
+    req: HttpRequest,
+    params: web::Query<Params>,
+    cache: web::Data<cache::ResultCache>,
+    metrics: web::Data<metrics::Metrics>,
+    limits: web::Data<limits::Limits>,
+    profiles: web::Data<profiles::Profiles>,
+) -> Result<HttpResponse, Error> {
+    let params = params.into_inner();
+    let url = params
+        .url
+        .as_deref()
+        .ok_or_else(|| error::ErrorBadRequest("a GTFS url parameter is needed"))?;
+    let custom_rules = profiles.get(params.profile.as_deref())?;
+    if params.callback_url.is_some() {
+        limits.check_url(url)?;
+        return enqueue(
+            &req,
+            jobs::JobInput::Url(url.to_owned()),
+            &params,
+            custom_rules,
+        );
+    }
+    let permit = limits
+        .try_acquire()
+        .ok_or_else(limits::Limits::saturated_error)?;
+    let archive = download(url, &metrics, &limits).await?;
+    validate_archive(
+        &req,
+        archive,
+        &params,
+        custom_rules,
+        &cache,
+        &limits,
+        permit,
+    )
+    .await
+}
+
+/// Validates the archive sent as body, or as the `file` field of a `multipart/form-data` form.
+#[post("/validate")]
+async fn validate_post(
+    req: HttpRequest,
+    params: web::Query<Params>,
+    payload: web::Payload,
+    cache: web::Data<cache::ResultCache>,
+    metrics: web::Data<metrics::Metrics>,
+    limits: web::Data<limits::Limits>,
+    profiles: web::Data<profiles::Profiles>,
+) -> Result<HttpResponse, Error> {
+    let mut params = params.into_inner();
+    // the slot is reserved before reading the upload, a saturated dæmon doesn't receive it
+    let permit = match params.callback_url {
+        Some(_) => None,
+        None => Some(
+            limits
+                .try_acquire()
+                .ok_or_else(limits::Limits::saturated_error)?,
+        ),
+    };
+    let max_upload_size = limits.settings.max_upload_size;
+    let body = if req.content_type() == "multipart/form-data" {
+        ui::read_form(&req, payload, max_upload_size, &mut params).await?
+    } else {
+        read_payload(payload, max_upload_size).await?
+    };
+    let custom_rules = profiles.get(params.profile.as_deref())?;
+    if params.callback_url.is_some() {
+        let input = match &params.url {
+            Some(url) if body.is_empty() => {
+                limits.check_url(url)?;
+                jobs::JobInput::Url(url.clone())
+            }
+            _ => jobs::JobInput::Archive(body.to_vec()),
+        };
+        // a form giving a callback_url is queued, its slot is released
+        return enqueue(&req, input, &params, custom_rules);
+    }
+    let permit = permit
+        .or_else(|| limits.try_acquire())
+        .ok_or_else(limits::Limits::saturated_error)?;
+    let archive = match &params.url {
+        // a form can give an url instead of a file
+        Some(url) if body.is_empty() => download(url, &metrics, &limits).await?,
+        _ => Ok(body.freeze()),
+    };
+    validate_archive(
+        &req,
+        archive,
+        &params,
+        custom_rules,
+        &cache,
+        &limits,
+        permit,
+    )
+    .await
+}
+
+/// Queues the validation as a job, for a request with a `callback_url`.
+fn enqueue(
+    req: &HttpRequest,
+    input: jobs::JobInput,
+    params: &Params,
+    custom_rules: Arc<custom_rules::CustomRules>,
+) -> Result<HttpResponse, Error> {
+    let queue = req
+        .app_data::<web::Data<jobs::JobQueue>>()
+        .ok_or_else(|| error::ErrorNotImplemented("validation jobs are not available"))?;
+    queue.enqueue(
+        req,
+        input,
+        custom_rules,
+        params.max_size.unwrap_or(1000),
+        params.no_cache,
+        params.callback_url.as_deref(),
+    )
+}
+
+/// Downloads a GTFS.
+///
+/// A GTFS that cannot be fetched is not an error of the request, but an issue of the validation.
+async fn download(
+    url: &str,
+    metrics: &metrics::Metrics,
+    limits: &limits::Limits,
+) -> Result<Result<web::Bytes, gtfs_structures::Error>, Error> {
+    log::info!("Starting validation: {}", url);
+    let start = Instant::now();
+    let archive = limits.download(url).await;
+    metrics.observe_download(archive.is_ok(), start.elapsed());
+    match archive {
+        Ok(archive) => Ok(Ok(archive)),
+        Err(limits::DownloadError::Fetch(e)) => Ok(Err(e)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn validate_archive(
+    req: &HttpRequest,
+    archive: Result<web::Bytes, gtfs_structures::Error>,
+    params: &Params,
+    custom_rules: Arc<custom_rules::CustomRules>,
+    cache: &web::Data<cache::ResultCache>,
+    limits: &limits::Limits,
+    permit: limits::ValidationPermit,
+) -> Result<HttpResponse, Error> {
+    let max_size = params.max_size.unwrap_or(1000);
+    let format = params.format;
+    let archive = match archive {
+        Ok(archive) => archive,
+        Err(e) => {
+            let json = serde_json::to_vec(&process(Err(e), max_size, &custom_rules))?;
+            return Ok(HttpResponse::Ok()
+                .content_type(format.content_type())
+                .body(format.render(&json.into())?));
+        }
+    };
+    let no_cache = params.no_cache;
+    let result = {
+        let cache = cache.clone();
+        limits
+            .run(permit, move || {
+                cache.validate(&archive, max_size, &custom_rules, no_cache, &())
+            })
+            .await?
+    };
+    log::info!("Finished validation");
+    cache.respond(req, result, format)
+}
+
+/// Reads a body, failing with a `413 Payload Too Large` above `max_size` bytes.
+async fn read_payload<S, E>(mut payload: S, max_size: usize) -> Result<web::BytesMut, Error>
+where
+    S: Stream<Item = Result<web::Bytes, E>> + Unpin,
+    E: Into<Error>,
+{
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(Into::into)?;
+        if body.len() + chunk.len() > max_size {
+            return Err(error::ErrorPayloadTooLarge(format!(
+                "the GTFS is larger than the maximum size of {} bytes",
+                max_size
+            )));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// Reads a setting from an environment variable, using `default` if it is not set or invalid.
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Runs the dæmon until it receives a termination signal.
+pub fn run_server(config: Config) -> anyhow::Result<()> {
+    run_server_impl(config)
+}
+
+#[actix_rt::main]
+async fn run_server_impl(config: Config) -> anyhow::Result<()> {
+    let server_settings = config.server;
+    let addr = format!("{}:{}", server_settings.bind, server_settings.port);
+    let shutdown_timeout = server_settings.shutdown_timeout;
+    let metrics = web::Data::new(metrics::Metrics::new());
+    let cache = web::Data::new(cache::ResultCache::new(
+        config.cache,
+        metrics.clone().into_inner(),
+    ));
+    let limits = web::Data::new(limits::Limits::new(config.limits));
+    let profiles = web::Data::new(profiles::Profiles::new(&config.profiles)?);
+    let api_keys = web::Data::new(auth::ApiKeys::from_settings(config.api_keys)?);
+    let jobs = web::Data::new(jobs::JobQueue::new(
+        config.jobs,
+        cache.clone().into_inner(),
+        metrics.clone().into_inner(),
+        limits.clone().into_inner(),
+    ));
+
+    let app_jobs = jobs.clone();
+    let mut server = HttpServer::new(move || {
+        let request_metrics = metrics.clone();
+        App::new()
+            .app_data(metrics.clone())
+            .app_data(cache.clone())
+            .app_data(app_jobs.clone())
+            .app_data(limits.clone())
+            .app_data(profiles.clone())
+            .app_data(api_keys.clone())
+            .wrap(actix_web::middleware::from_fn(auth::authenticate))
+            .wrap_fn(move |req, srv| {
+                let in_flight = request_metrics.track_request();
+                srv.call(req).map(move |res| {
+                    drop(in_flight);
+                    res
+                })
+            })
+            .service(validate)
+            .service(ui::index)
+            .service(validate_post)
+            .service(metrics::export)
+            .service(openapi::openapi)
+            .configure(jobs::configure)
+            .configure(status::configure)
+    })
+    // on SIGTERM or SIGINT, the requests being handled, and so the synchronous
+    // validations, are given this time to finish
+    .shutdown_timeout(shutdown_timeout.as_secs());
+    if let Some(workers) = server_settings.workers {
+        server = server.workers(workers);
+    }
+    let server = server
+        .bind(&addr)
+        .with_context(|| format!("impossible to bind the address {}", addr))?;
+    log::info!("Listening on {}", addr);
+    server.run().await?;
+
+    log::info!("Stopping the validation jobs");
+    web::block(move || jobs.drain(shutdown_timeout)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::limits::{LimitSettings, Limits};
+    use std::io::{Cursor, Write};
+    use std::path::Path;
+    use std::time::Duration;
+    use walkdir::WalkDir;
+    use zip::{ZipWriter, write::FileOptions};
+
+    // zip a directory of the test data, to be sent as a request body
+    pub fn zip_directory(dir_path: &str) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut zw = ZipWriter::new(Cursor::new(&mut data));
+        for entry in WalkDir::new(dir_path).into_iter().flatten() {
+            let path = entry.path();
+            let name = path
+                .strip_prefix(Path::new(dir_path))
+                .unwrap()
+                .to_str()
+                .unwrap();
+            if path.is_file() {
+                zw.start_file(name, FileOptions::<()>::default()).unwrap();
+                zw.write_all(&std::fs::read(path).unwrap()).unwrap();
+            }
+        }
+        zw.finish().unwrap();
+        data
+    }
+
+    // limits small enough to be reached by the tests
+    pub fn limits() -> Limits {
+        Limits::new(LimitSettings {
+            max_upload_size: 100_000,
+            max_download_size: 100_000,
+            download_timeout: Duration::from_secs(1),
+            validation_timeout: Duration::from_secs(10),
+            max_concurrent_validations: 2,
+            allowed_hosts: vec![],
+            denied_hosts: vec!["localhost".to_owned(), "example.com".to_owned()],
+        })
+    }
+
+    #[test]
+    fn test_bind_error() {
+        // the port is already used
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut config = super::Config::default();
+        config.server.port = listener.local_addr().unwrap().port();
+        let error = super::run_server(config).unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("impossible to bind the address 127.0.0.1:")
+        );
+    }
+}
@@ -0,0 +1,343 @@
+//! OpenAPI 3 description of the dæmon API, served on `GET /openapi.json`.
+//!
+//! The schemas of the responses are derived from the serialized types.
+use super::callbacks::{Notification, SIGNATURE_HEADER};
+use super::jobs::Job;
+use super::status::Version;
+use crate::progress::Step;
+use crate::validate::Response;
+use actix_web::{get, web};
+use schemars::generate::SchemaSettings;
+use serde_json::{Value, json};
+
+/// The OpenAPI document of the dæmon.
+pub fn document() -> Value {
+    let mut generator = SchemaSettings::openapi3().for_serialize().into_generator();
+    let response = generator.subschema_for::<Response>();
+    let job = generator.subschema_for::<Job>();
+    let version = generator.subschema_for::<Version>();
+    let notification = generator.subschema_for::<Notification>();
+    let step = generator.subschema_for::<Step>();
+    let schemas = generator.take_definitions(true);
+
+    let parameter = |name: &str, description: &str, schema: Value| {
+        json!({
+            "name": name,
+            "in": "query",
+            "description": description,
+            "schema": schema,
+        })
+    };
+    let max_size = parameter(
+        "max_size",
+        "Maximum number of issues per type",
+        json!({"type": "integer", "minimum": 0, "default": 1000}),
+    );
+    let no_cache = parameter(
+        "no_cache",
+        "If true, the result is not read from the cache",
+        json!({"type": "boolean", "default": false}),
+    );
+    let profile = parameter(
+        "profile",
+        "Name of the custom rules to use",
+        json!({"type": "string", "default": "default"}),
+    );
+    let format = parameter(
+        "format",
+        "Output format of the result",
+        json!({
+            "type": "string",
+            "enum": ["json", "pretty_json", "yaml", "html"],
+            "default": "json",
+        }),
+    );
+    let url = parameter(
+        "url",
+        "Url of the GTFS archive",
+        json!({"type": "string", "format": "uri"}),
+    );
+    let callback_url = parameter(
+        "callback_url",
+        "If set, the validation is queued as a job, and this url is notified when it is finished",
+        json!({"type": "string", "format": "uri"}),
+    );
+    let mut required_url = url.clone();
+    required_url["required"] = json!(true);
+    let job_id = json!({
+        "name": "id",
+        "in": "path",
+        "required": true,
+        "schema": {"type": "string", "format": "uuid"},
+    });
+
+    let result = json!({
+        "description": "Result of the validation",
+        "headers": {
+            "ETag": {"schema": {"type": "string"}},
+            "X-Cache": {"schema": {"type": "string", "enum": ["HIT", "MISS", "BYPASS"]}},
+        },
+        "content": {
+            "application/json": {"schema": response},
+            "application/yaml": {"schema": {"type": "string"}},
+            "text/html": {"schema": {"type": "string"}},
+        },
+    });
+    let error = |description: &str| json!({"description": description});
+    let archive = json!({
+        "application/zip": {"schema": {"type": "string", "format": "binary"}},
+        "application/octet-stream": {"schema": {"type": "string", "format": "binary"}},
+    });
+    let mut validate_post_body = archive.clone();
+    validate_post_body["multipart/form-data"] = json!({
+        "schema": {
+            "type": "object",
+            "properties": {
+                "file": {"type": "string", "format": "binary"},
+                "url": {"type": "string", "format": "uri"},
+                "profile": {"type": "string"},
+                "format": {"type": "string"},
+                "max_size": {"type": "integer"},
+                "no_cache": {"type": "boolean"},
+                "callback_url": {"type": "string", "format": "uri"},
+            },
+        },
+    });
+    let job_created = json!({
+        "description": "The job has been queued",
+        "headers": {"Location": {"schema": {"type": "string"}}},
+        "content": {"application/json": {"schema": job}},
+    });
+    let callbacks = json!({
+        "finished": {
+            "{$request.query.callback_url}": {
+                "post": {
+                    "summary": "Notification of the end of the job",
+                    "parameters": [{
+                        "name": SIGNATURE_HEADER,
+                        "in": "header",
+                        "description": "sha256= followed by the hex HMAC-SHA256 of the body, if a secret is configured",
+                        "schema": {"type": "string"},
+                    }],
+                    "requestBody": {
+                        "required": true,
+                        "content": {"application/json": {"schema": notification}},
+                    },
+                    "responses": {"2XX": error("The notification has been received")},
+                },
+            },
+        },
+    });
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "GTFS validator",
+            "description": "Validation of GTFS (General Transit Feed Specification) files",
+            "version": env!("CARGO_PKG_VERSION"),
+            "license": {"name": "MIT"},
+        },
+        "paths": {
+            "/validate": {
+                "get": {
+                    "summary": "Downloads and validates a GTFS",
+                    "operationId": "validate",
+                    "parameters": [required_url, max_size, no_cache, profile, format, callback_url],
+                    "responses": {
+                        "200": result,
+                        "202": job_created,
+                        "304": error("The client already has this result"),
+                        "400": error("Invalid parameters"),
+                        "403": error("Downloads from this host are not allowed"),
+                        "413": error("The GTFS is too large"),
+                        "503": error("Too many validations are running"),
+                        "504": error("The validation took too long"),
+                    },
+                    "callbacks": callbacks.clone(),
+                },
+                "post": {
+                    "summary": "Validates a GTFS sent as body or as the file field of a form",
+                    "operationId": "validatePost",
+                    "parameters": [max_size, no_cache, profile, format, callback_url],
+                    "requestBody": {"required": true, "content": validate_post_body},
+                    "responses": {
+                        "200": result,
+                        "202": job_created,
+                        "304": error("The client already has this result"),
+                        "400": error("Invalid parameters"),
+                        "413": error("The GTFS is too large"),
+                        "503": error("Too many validations are running"),
+                        "504": error("The validation took too long"),
+                    },
+                    "callbacks": callbacks.clone(),
+                },
+            },
+            "/jobs": {
+                "post": {
+                    "summary": "Queues the validation of the GTFS at url, or sent as body",
+                    "operationId": "createJob",
+                    "parameters": [url, max_size, no_cache, profile, callback_url],
+                    "requestBody": {"required": false, "content": archive},
+                    "responses": {
+                        "202": job_created,
+                        "400": error("Neither an url nor an archive has been given"),
+                        "403": error("Downloads from this host are not allowed"),
+                        "413": error("The GTFS is too large"),
+                        "503": error("Too many jobs are waiting"),
+                    },
+                    "callbacks": callbacks,
+                },
+            },
+            "/jobs/{id}": {
+                "get": {
+                    "summary": "State of a validation job",
+                    "operationId": "getJob",
+                    "parameters": [job_id],
+                    "responses": {
+                        "200": {
+                            "description": "State of the job",
+                            "content": {"application/json": {"schema": job}},
+                        },
+                        "404": error("Unknown or expired job"),
+                    },
+                },
+            },
+            "/jobs/{id}/result": {
+                "get": {
+                    "summary": "Result of a finished validation job",
+                    "operationId": "getJobResult",
+                    "parameters": [job_id],
+                    "responses": {
+                        "200": {
+                            "description": "Result of the validation",
+                            "content": {"application/json": {"schema": response}},
+                        },
+                        "202": {
+                            "description": "The job is not finished yet",
+                            "content": {"application/json": {"schema": job}},
+                        },
+                        "404": error("Unknown or expired job"),
+                        "422": {
+                            "description": "The job has failed",
+                            "content": {"application/json": {"schema": job}},
+                        },
+                    },
+                },
+            },
+            "/jobs/{id}/events": {
+                "get": {
+                    "summary": "Progress of a validation job, as Server-Sent Events",
+                    "description": "A `step` event is sent when a step of the validation starts, and when it is finished. The stream ends with a `finished` or `failed` event holding the job.",
+                    "operationId": "getJobEvents",
+                    "parameters": [job_id],
+                    "responses": {
+                        "200": {
+                            "description": "Stream of events, whose data are steps or the job",
+                            "content": {
+                                "text/event-stream": {"schema": {"oneOf": [step, job]}},
+                            },
+                        },
+                        "404": error("Unknown or expired job"),
+                    },
+                },
+            },
+            "/health": {
+                "get": {
+                    "summary": "Liveness probe",
+                    "operationId": "health",
+                    "responses": {"200": error("The dæmon is running")},
+                },
+            },
+            "/ready": {
+                "get": {
+                    "summary": "Readiness probe",
+                    "operationId": "ready",
+                    "responses": {
+                        "200": error("A validation can be queued, and run synchronously"),
+                        "503": error("The job queue is full, or too many validations are running"),
+                    },
+                },
+            },
+            "/version": {
+                "get": {
+                    "summary": "Version and capabilities of the validator",
+                    "operationId": "version",
+                    "responses": {
+                        "200": {
+                            "description": "Version of the validator",
+                            "content": {"application/json": {"schema": version}},
+                        },
+                    },
+                },
+            },
+            "/metrics": {
+                "get": {
+                    "summary": "Metrics in the Prometheus text format",
+                    "operationId": "metrics",
+                    "responses": {
+                        "200": {
+                            "description": "Metrics",
+                            "content": {"text/plain": {"schema": {"type": "string"}}},
+                        },
+                    },
+                },
+            },
+        },
+        // the keys are only needed if the dæmon is configured with some
+        "security": [{}, {"bearer": []}, {"api_key": []}],
+        "components": {
+            "schemas": schemas,
+            "securitySchemes": {
+                "bearer": {"type": "http", "scheme": "bearer"},
+                "api_key": {"type": "apiKey", "in": "header", "name": "X-API-Key"},
+            },
+        },
+    })
+}
+
+#[get("/openapi.json")]
+async fn openapi() -> web::Json<Value> {
+    web::Json(document())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document() {
+        let document = document();
+        assert_eq!(
+            "#/components/schemas/Response",
+            document["paths"]["/validate"]["get"]["responses"]["200"]["content"]["application/json"]
+                ["schema"]["$ref"]
+        );
+        let schemas = &document["components"]["schemas"];
+        for name in [
+            "Response",
+            "Metadata",
+            "Stats",
+            "Issue",
+            "IssueType",
+            "Job",
+            "Notification",
+            "Step",
+        ] {
+            assert!(schemas[name].is_object(), "{} schema is missing", name);
+        }
+        assert!(
+            schemas["Issue"]["required"]
+                .as_array()
+                .unwrap()
+                .contains(&"severity".into())
+        );
+        // the keywords of JSON Schema missing from OpenAPI 3.0 are not used
+        assert!(!document.to_string().contains("propertyNames"));
+        // all the references have a definition
+        let document = document.to_string();
+        for reference in document.split("\"$ref\":\"#/components/schemas/").skip(1) {
+            let name = &reference[..reference.find('"').unwrap()];
+            assert!(schemas[name].is_object(), "{} is not defined", name);
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Named sets of custom rules the clients of the dæmon can choose from.
+use super::env_or;
+use crate::custom_rules::CustomRules;
+use actix_web::{Error, error};
+use anyhow::Context;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Settings of the profiles.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileSettings {
+    /// Directory of the custom rules files of the profiles.
+    pub dir: Option<PathBuf>,
+    /// Profile used when none is asked for.
+    pub default: String,
+    /// Custom rules file of the `default` profile.
+    pub default_rules: Option<PathBuf>,
+}
+
+impl Default for ProfileSettings {
+    fn default() -> Self {
+        ProfileSettings {
+            dir: None,
+            default: DEFAULT_PROFILE.to_owned(),
+            default_rules: None,
+        }
+    }
+}
+
+impl ProfileSettings {
+    /// Overrides the settings with the environment variables.
+    pub fn with_env(self) -> Self {
+        ProfileSettings {
+            dir: std::env::var("PROFILES_DIR")
+                .ok()
+                .map(PathBuf::from)
+                .or(self.dir),
+            default: env_or("DEFAULT_PROFILE", self.default),
+            default_rules: std::env::var("DEFAULT_RULES")
+                .ok()
+                .map(PathBuf::from)
+                .or(self.default_rules),
+        }
+    }
+}
+
+pub struct Profiles {
+    profiles: BTreeMap<String, Arc<CustomRules>>,
+    /// Name of the profile used when none is asked for.
+    default: String,
+}
+
+impl Profiles {
+    /// Loads the profiles from the YAML custom rules files of the directory of the settings.
+    ///
+    /// Fails if the default profile does not exist, or if its rules cannot be read.
+    pub fn new(settings: &ProfileSettings) -> anyhow::Result<Self> {
+        let mut profiles = match &settings.dir {
+            Some(dir) => Self::from_dir(dir),
+            None => Self::default(),
+        };
+        if let Some(path) = &settings.default_rules {
+            let file = std::fs::File::open(path)
+                .with_context(|| format!("impossible to read the default rules {:?}", path))?;
+            let rules = serde_norway::from_reader(file)
+                .with_context(|| format!("invalid default rules {:?}", path))?;
+            profiles
+                .profiles
+                .insert(DEFAULT_PROFILE.to_owned(), Arc::new(rules));
+        }
+        anyhow::ensure!(
+            profiles.profiles.contains_key(&settings.default),
+            "the default profile {} does not exist",
+            settings.default
+        );
+        profiles.default = settings.default.clone();
+        Ok(profiles)
+    }
+
+    /// Each `name.yml` file of the directory is a profile called `name`.
+    ///
+    /// A `default` profile, without any custom rule, exists if it is not defined in the directory.
+    pub fn from_dir(dir: &Path) -> Self {
+        let mut profiles = Profiles::default();
+        for path in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+            let path = path.path();
+            if !path.extension().is_some_and(|e| e == "yml" || e == "yaml") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            let rules = std::fs::File::open(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|f| serde_norway::from_reader(f).map_err(|e| e.to_string()));
+            match rules {
+                Ok(rules) => {
+                    log::info!("Loaded the profile {}", name);
+                    profiles.profiles.insert(name.to_owned(), Arc::new(rules));
+                }
+                Err(e) => log::error!("impossible to load the profile {:?}: {}", path, e),
+            }
+        }
+        profiles
+    }
+
+    /// The custom rules of a profile, or of the default one if no profile is asked for.
+    pub fn get(&self, name: Option<&str>) -> Result<Arc<CustomRules>, Error> {
+        let name = name.filter(|n| !n.is_empty()).unwrap_or(&self.default);
+        self.profiles
+            .get(name)
+            .cloned()
+            .ok_or_else(|| error::ErrorBadRequest(format!("unknown profile {}", name)))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    /// Name of the profile used when none is asked for.
+    pub fn default_name(&self) -> &str {
+        &self.default
+    }
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Profiles {
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.to_owned(), Arc::default())]),
+            default: DEFAULT_PROFILE.to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        let profiles = Profiles::from_dir(Path::new("test_data/custom_rules"));
+        // bad_custom_rules.yml is not a valid profile
+        assert_eq!(
+            vec!["custom_rules", "default"],
+            profiles.names().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(10.),
+            profiles.get(Some("custom_rules")).unwrap().max_bus_speed
+        );
+        assert_eq!(None, profiles.get(None).unwrap().max_bus_speed);
+        assert!(profiles.get(Some("unknown")).is_err());
+
+        let settings = ProfileSettings {
+            dir: Some("test_data/custom_rules".into()),
+            default: "custom_rules".to_owned(),
+            default_rules: None,
+        };
+        let profiles = Profiles::new(&settings).unwrap();
+        assert_eq!("custom_rules", profiles.default_name());
+        assert_eq!(Some(10.), profiles.get(None).unwrap().max_bus_speed);
+
+        let settings = ProfileSettings {
+            default_rules: Some("test_data/custom_rules/custom_rules.yml".into()),
+            ..ProfileSettings::default()
+        };
+        let profiles = Profiles::new(&settings).unwrap();
+        assert_eq!(
+            Some(10.),
+            profiles.get(Some("default")).unwrap().max_bus_speed
+        );
+
+        let settings = ProfileSettings {
+            default: "unknown".to_owned(),
+            ..ProfileSettings::default()
+        };
+        assert!(Profiles::new(&settings).is_err());
+    }
+}
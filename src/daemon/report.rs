@@ -0,0 +1,173 @@
+//! Output formats of the validation results, including a HTML rendering of the report.
+use actix_web::http::header::ContentType;
+use actix_web::{Error, error, web::Bytes};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt::Write;
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    PrettyJson,
+    Yaml,
+    Html,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(Value::String(s.to_owned()))
+            .map_err(|_| error::ErrorBadRequest(format!("unknown output format {}", s)))
+    }
+}
+
+impl OutputFormat {
+    pub fn content_type(&self) -> ContentType {
+        match self {
+            OutputFormat::Json | OutputFormat::PrettyJson => ContentType::json(),
+            OutputFormat::Yaml => ContentType(
+                "application/yaml"
+                    .parse()
+                    .expect("application/yaml is a valid mime type"),
+            ),
+            OutputFormat::Html => ContentType::html(),
+        }
+    }
+
+    /// Converts the serialized [Response] to this format.
+    ///
+    /// [Response]: ../../validate/struct.Response.html
+    pub fn render(&self, json: &Bytes) -> Result<Bytes, Error> {
+        let response = || serde_json::from_slice::<Value>(json);
+        let rendered = match self {
+            OutputFormat::Json => return Ok(json.clone()),
+            OutputFormat::PrettyJson => serde_json::to_string_pretty(&response()?)?,
+            OutputFormat::Yaml => {
+                serde_norway::to_string(&response()?).map_err(error::ErrorInternalServerError)?
+            }
+            OutputFormat::Html => render_html(&response()?),
+        };
+        Ok(Bytes::from(rendered))
+    }
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => escape(s),
+        Value::Array(values) => values.iter().map(text).collect::<Vec<_>>().join(", "),
+        other => escape(&other.to_string()),
+    }
+}
+
+/// A standalone HTML page of the validation report.
+pub fn render_html(response: &Value) -> String {
+    let mut html = String::from(concat!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n",
+        "<title>GTFS validation report</title>\n<style>",
+        include_str!("style.css"),
+        "</style>\n</head>\n<body>\n<h1>GTFS validation report</h1>\n",
+    ));
+
+    let metadata = &response["metadata"];
+    if metadata.is_object() {
+        html.push_str("<h2>Summary</h2>\n<table>\n");
+        for (label, value) in [
+            ("Start date", &metadata["start_date"]),
+            ("End date", &metadata["end_date"]),
+            ("Networks", &metadata["networks"]),
+            ("Modes", &metadata["modes"]),
+            ("Stops", &metadata["stats"]["stops_count"]),
+            ("Routes", &metadata["stats"]["routes_count"]),
+            ("Trips", &metadata["stats"]["trips_count"]),
+            ("Validator version", &metadata["validator_version"]),
+        ] {
+            let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", label, text(value));
+        }
+        html.push_str("</table>\n");
+    }
+
+    let validations = response["validations"].as_object();
+    match validations {
+        Some(validations) if !validations.is_empty() => {
+            html.push_str("<h2>Issues</h2>\n");
+            for (issue_type, issues) in validations {
+                let issues = issues.as_array().map(Vec::as_slice).unwrap_or_default();
+                let _ = writeln!(
+                    html,
+                    "<h3>{} ({})</h3>\n<table>\n<tr><th>Severity</th><th>Object</th><th>Name</th><th>Related objects</th><th>Details</th></tr>",
+                    escape(issue_type),
+                    issues.len()
+                );
+                for issue in issues {
+                    let severity = text(&issue["severity"]);
+                    let related: Vec<String> = issue["related_objects"]
+                        .as_array()
+                        .map(Vec::as_slice)
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|o| text(&o["id"]))
+                        .collect();
+                    let _ = writeln!(
+                        html,
+                        "<tr><td class=\"{}\">{}</td><td>{} {}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                        severity.to_lowercase(),
+                        severity,
+                        text(&issue["object_type"]),
+                        text(&issue["object_id"]),
+                        text(&issue["object_name"]),
+                        related.join(", "),
+                        text(&issue["details"]),
+                    );
+                }
+                html.push_str("</table>\n");
+            }
+        }
+        _ => html.push_str("<p>No issue has been found.</p>\n"),
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::custom_rules::CustomRules;
+    use crate::validate::validate;
+
+    #[test]
+    fn test_formats() {
+        let json = validate("test_data/unused_stop", 1000, &CustomRules::default()).unwrap();
+        let json = Bytes::from(json);
+
+        assert_eq!(json, OutputFormat::Json.render(&json).unwrap());
+        let yaml = OutputFormat::Yaml.render(&json).unwrap();
+        assert!(std::str::from_utf8(&yaml).unwrap().contains("UnusedStop:"));
+        let html = OutputFormat::Html.render(&json).unwrap();
+        let html = std::str::from_utf8(&html).unwrap();
+        assert!(html.contains("<h3>UnusedStop (1)</h3>"));
+        assert!(html.contains("<td class=\"information\">Information</td><td>Stop "));
+
+        assert_eq!(OutputFormat::PrettyJson, "pretty_json".parse().unwrap());
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            "&lt;script&gt;alert(&quot;&amp;&#39;)&lt;/script&gt;",
+            escape("<script>alert(\"&')</script>")
+        );
+    }
+}
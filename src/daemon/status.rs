@@ -0,0 +1,147 @@
+//! Probes for the orchestrator, and description of the running validator.
+use super::jobs::JobQueue;
+use super::limits::Limits;
+use crate::issues::IssueType;
+use actix_web::{HttpResponse, get, web};
+use schemars::JsonSchema;
+use serde::Serialize;
+use strum::IntoEnumIterator;
+
+/// Description of the validator, as returned by `GET /version`.
+#[derive(Serialize, JsonSchema)]
+pub struct Version {
+    pub version: &'static str,
+    /// Cargo features the validator has been built with.
+    pub features: Vec<&'static str>,
+    /// All the issue types the validator can report.
+    pub issue_types: Vec<IssueType>,
+}
+
+impl Version {
+    pub fn new() -> Self {
+        let features = [
+            ("daemon", cfg!(feature = "daemon")),
+            ("jemalloc", cfg!(feature = "jemalloc")),
+            ("read-url", cfg!(feature = "read-url")),
+        ];
+        Version {
+            version: env!("CARGO_PKG_VERSION"),
+            features: features
+                .into_iter()
+                .filter_map(|(name, enabled)| enabled.then_some(name))
+                .collect(),
+            issue_types: IssueType::iter().collect(),
+        }
+    }
+}
+
+/// Liveness probe: the dæmon answers.
+#[get("/health")]
+async fn health() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({"status": "ok"}))
+}
+
+/// Readiness probe: a validation can be queued, and a synchronous one can be run.
+#[get("/ready")]
+async fn ready(queue: web::Data<JobQueue>, limits: web::Data<Limits>) -> HttpResponse {
+    if queue.is_saturated() || limits.is_saturated() {
+        HttpResponse::ServiceUnavailable().json(serde_json::json!({"status": "saturated"}))
+    } else {
+        HttpResponse::Ok().json(serde_json::json!({"status": "ready"}))
+    }
+}
+
+#[get("/version")]
+async fn version() -> web::Json<Version> {
+    web::Json(Version::new())
+}
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(health).service(ready).service(version);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::cache::{CacheSettings, ResultCache};
+    use crate::daemon::jobs::JobSettings;
+    use crate::daemon::tests::limits;
+    use actix_web::App;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{TestRequest, call_and_read_body_json, call_service, init_service};
+    use std::sync::Arc;
+
+    fn job_queue(queue_size: usize) -> JobQueue {
+        let metrics = Arc::default();
+        let cache = ResultCache::new(
+            CacheSettings {
+                size: 0,
+                dir: None,
+                dir_size: 0,
+                max_age: 0,
+            },
+            Arc::clone(&metrics),
+        );
+        let settings = JobSettings {
+            workers: 1,
+            queue_size,
+            ..JobSettings::default()
+        };
+        JobQueue::new(settings, Arc::new(cache), metrics, Arc::new(limits()))
+    }
+
+    #[actix_web::test]
+    async fn test_probes() {
+        let limits = web::Data::new(limits());
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(job_queue(10)))
+                .app_data(limits.clone())
+                .configure(configure),
+        )
+        .await;
+        let req = TestRequest::get().uri("/health").to_request();
+        assert_eq!(StatusCode::OK, call_service(&app, req).await.status());
+        let req = TestRequest::get().uri("/ready").to_request();
+        assert_eq!(StatusCode::OK, call_service(&app, req).await.status());
+
+        // all the synchronous validations are running
+        let permits = (limits.try_acquire(), limits.try_acquire());
+        let req = TestRequest::get().uri("/ready").to_request();
+        assert_eq!(
+            StatusCode::SERVICE_UNAVAILABLE,
+            call_service(&app, req).await.status()
+        );
+        drop(permits);
+
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(job_queue(0)))
+                .app_data(limits)
+                .configure(configure),
+        )
+        .await;
+        let req = TestRequest::get().uri("/ready").to_request();
+        assert_eq!(
+            StatusCode::SERVICE_UNAVAILABLE,
+            call_service(&app, req).await.status()
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_version() {
+        let app = init_service(App::new().configure(configure)).await;
+        let req = TestRequest::get().uri("/version").to_request();
+        let body: serde_json::Value = call_and_read_body_json(&app, req).await;
+        assert_eq!(env!("CARGO_PKG_VERSION"), body["version"]);
+        assert!(
+            body["features"]
+                .as_array()
+                .unwrap()
+                .contains(&"daemon".into())
+        );
+        let issue_types = body["issue_types"].as_array().unwrap();
+        assert!(issue_types.contains(&"UnusedStop".into()));
+        assert!(issue_types.contains(&"InvalidArchive".into()));
+    }
+}
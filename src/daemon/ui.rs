@@ -0,0 +1,202 @@
+//! Web interface of the dæmon: a page with a form to validate a GTFS.
+use super::auth::ApiKeys;
+use super::profiles::Profiles;
+use super::report::escape;
+use super::{Params, read_payload};
+use actix_multipart::Multipart;
+use actix_web::{Error, HttpRequest, HttpResponse, error, get, web};
+use futures::StreamExt;
+
+/// Maximum size of the form fields other than the GTFS archive.
+const MAX_FIELD_SIZE: usize = 10_000;
+
+#[get("/")]
+async fn index(profiles: web::Data<Profiles>, keys: Option<web::Data<ApiKeys>>) -> HttpResponse {
+    let options: String = profiles
+        .names()
+        .map(|name| {
+            let selected = if name == profiles.default_name() {
+                " selected"
+            } else {
+                ""
+            };
+            format!("<option{}>{}</option>\n", selected, escape(name))
+        })
+        .collect();
+    // a form cannot send the API key, the validations then go through the API only
+    let form = if keys.is_none_or(|keys| keys.is_open()) {
+        include_str!("form.html")
+    } else {
+        "<p>The validations need an API key, sent in an <code>Authorization: Bearer …</code> \
+         or a <code>X-API-Key</code> header.</p>"
+    };
+    let page = include_str!("index.html")
+        .replace("{style}", include_str!("style.css"))
+        .replace("{form}", form)
+        .replace("{profiles}", &options);
+    HttpResponse::Ok()
+        .content_type(actix_web::http::header::ContentType::html())
+        .body(page)
+}
+
+/// Reads a `multipart/form-data` body and returns the GTFS archive sent in its `file` field.
+///
+/// The other fields of the form override the parameters of the query string.
+pub async fn read_form(
+    req: &HttpRequest,
+    payload: web::Payload,
+    max_size: usize,
+    params: &mut Params,
+) -> Result<web::BytesMut, Error> {
+    let mut form = Multipart::new(req.headers(), payload);
+    let mut archive = web::BytesMut::new();
+    while let Some(field) = form.next().await {
+        let field = field?;
+        let name = field.name().unwrap_or_default().to_owned();
+        if name == "file" {
+            archive = read_payload(field, max_size).await?;
+            continue;
+        }
+        let value = read_payload(field, MAX_FIELD_SIZE).await?;
+        let value = std::str::from_utf8(&value)
+            .map_err(error::ErrorBadRequest)?
+            .trim();
+        // the fields left empty in the form are ignored
+        if value.is_empty() {
+            continue;
+        }
+        match name.as_str() {
+            "url" => params.url = Some(value.to_owned()),
+            "profile" => params.profile = Some(value.to_owned()),
+            "callback_url" => params.callback_url = Some(value.to_owned()),
+            "format" => params.format = value.parse()?,
+            "max_size" => params.max_size = Some(value.parse().map_err(error::ErrorBadRequest)?),
+            "no_cache" => params.no_cache = matches!(value, "true" | "on"),
+            _ => log::warn!("unknown form field {}", name),
+        }
+    }
+    Ok(archive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::cache::{CacheSettings, ResultCache};
+    use crate::daemon::metrics::Metrics;
+    use crate::daemon::tests::{limits, zip_directory};
+    use actix_web::App;
+    use actix_web::http::StatusCode;
+    use actix_web::http::header::{CONTENT_TYPE, HeaderValue};
+    use actix_web::test::{TestRequest, call_and_read_body, call_service, init_service};
+    use std::sync::Arc;
+
+    fn form(archive: &[u8], fields: &[(&str, &str)]) -> (HeaderValue, Vec<u8>) {
+        let boundary = "----validatorboundary";
+        let mut body = Vec::new();
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"gtfs.zip\"\r\nContent-Type: application/zip\r\n\r\n",
+                boundary
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(archive);
+        for (name, value) in fields {
+            body.extend_from_slice(
+                format!(
+                    "\r\n--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}",
+                    boundary, name, value
+                )
+                .as_bytes(),
+            );
+        }
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+        let content_type = format!("multipart/form-data; boundary={}", boundary);
+        (HeaderValue::from_str(&content_type).unwrap(), body)
+    }
+
+    #[actix_web::test]
+    async fn test_index_with_keys() {
+        let keys = ApiKeys::new(vec![crate::daemon::auth::ApiKey {
+            name: "client".to_owned(),
+            key: "secret".to_owned(),
+            rate_limit: None,
+            daily_quota: None,
+        }]);
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(keys))
+                .app_data(web::Data::new(Profiles::default()))
+                .service(index),
+        )
+        .await;
+        let req = TestRequest::get().uri("/").to_request();
+        let page = call_and_read_body(&app, req).await;
+        let page = std::str::from_utf8(&page).unwrap();
+        assert!(!page.contains("<form"));
+        assert!(page.contains("The validations need an API key"));
+    }
+
+    #[actix_web::test]
+    async fn test_form() {
+        let metrics = Arc::new(Metrics::new());
+        let cache = ResultCache::new(
+            CacheSettings {
+                size: 0,
+                dir: None,
+                dir_size: 0,
+                max_age: 0,
+            },
+            metrics.clone(),
+        );
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::from(metrics))
+                .app_data(web::Data::new(cache))
+                .app_data(web::Data::new(limits()))
+                .app_data(web::Data::new(Profiles::default()))
+                .service(index)
+                .service(crate::daemon::validate_post),
+        )
+        .await;
+
+        let req = TestRequest::get().uri("/").to_request();
+        let page = call_and_read_body(&app, req).await;
+        assert!(
+            std::str::from_utf8(&page)
+                .unwrap()
+                .contains("<option selected>default</option>")
+        );
+
+        let archive = zip_directory("test_data/unused_stop");
+        let (content_type, body) = form(&archive, &[("url", ""), ("format", "html")]);
+        let req = TestRequest::post()
+            .uri("/validate")
+            .insert_header((CONTENT_TYPE, content_type))
+            .set_payload(body)
+            .to_request();
+        let resp = call_service(&app, req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        assert_eq!(
+            "text/html; charset=utf-8",
+            resp.headers().get(CONTENT_TYPE).unwrap()
+        );
+        let report = actix_web::test::read_body(resp).await;
+        assert!(
+            std::str::from_utf8(&report)
+                .unwrap()
+                .contains("<h3>UnusedStop (1)</h3>")
+        );
+
+        let (content_type, body) = form(&archive, &[("profile", "unknown")]);
+        let req = TestRequest::post()
+            .uri("/validate")
+            .insert_header((CONTENT_TYPE, content_type))
+            .set_payload(body)
+            .to_request();
+        assert_eq!(
+            StatusCode::BAD_REQUEST,
+            call_service(&app, req).await.status()
+        );
+    }
+}
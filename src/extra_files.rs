@@ -0,0 +1,412 @@
+//! Files, and columns, of the GTFS that are not read by `gtfs_structures`.
+//!
+//! They are read from the same directory or zip archive as the `RawGtfs`. A file that is
+//! absent is `None`, like the optional files of the `RawGtfs`.
+use anyhow::Context;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use std::ffi::OsStr;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+
+/// A line of `transfers.txt`, with the route and trip ids `gtfs_structures` ignores.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct Transfer {
+    pub from_stop_id: Option<String>,
+    pub to_stop_id: Option<String>,
+    pub from_route_id: Option<String>,
+    pub to_route_id: Option<String>,
+    pub from_trip_id: Option<String>,
+    pub to_trip_id: Option<String>,
+    #[serde(default)]
+    pub transfer_type: gtfs_structures::TransferType,
+    pub min_transfer_time: Option<u32>,
+}
+
+/// A line of `levels.txt`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Level {
+    pub level_id: String,
+    pub level_index: f64,
+    pub level_name: Option<String>,
+}
+
+/// A line of `fare_leg_rules.txt`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct FareLegRule {
+    pub leg_group_id: Option<String>,
+    pub network_id: Option<String>,
+    pub from_area_id: Option<String>,
+    pub to_area_id: Option<String>,
+    pub from_timeframe_group_id: Option<String>,
+    pub to_timeframe_group_id: Option<String>,
+    pub fare_product_id: String,
+    pub rule_priority: Option<u32>,
+}
+
+/// A line of `fare_transfer_rules.txt`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct FareTransferRule {
+    pub from_leg_group_id: Option<String>,
+    pub to_leg_group_id: Option<String>,
+    pub transfer_count: Option<i32>,
+    pub duration_limit: Option<u32>,
+    pub duration_limit_type: Option<u8>,
+    pub fare_transfer_type: u8,
+    pub fare_product_id: Option<String>,
+}
+
+/// A line of `timeframes.txt`, its times in seconds since midnight.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Timeframe {
+    pub timeframe_group_id: String,
+    #[serde(default, deserialize_with = "optional_time")]
+    pub start_time: Option<u32>,
+    #[serde(default, deserialize_with = "optional_time")]
+    pub end_time: Option<u32>,
+    pub service_id: String,
+}
+
+/// A line of `areas.txt`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Area {
+    pub area_id: String,
+    pub area_name: Option<String>,
+}
+
+/// A line of `stop_areas.txt`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct StopArea {
+    pub area_id: String,
+    pub stop_id: String,
+}
+
+/// A line of `networks.txt`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Network {
+    pub network_id: String,
+    pub network_name: Option<String>,
+}
+
+/// A line of `route_networks.txt`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct RouteNetwork {
+    pub network_id: String,
+    pub route_id: String,
+}
+
+/// The columns of `stop_times.txt` describing GTFS-Flex services, read only if some are given.
+#[derive(Deserialize, Debug, Clone)]
+pub struct FlexStopTime {
+    pub trip_id: String,
+    pub stop_sequence: u32,
+    pub stop_id: Option<String>,
+    pub location_group_id: Option<String>,
+    pub location_id: Option<String>,
+    #[serde(default, deserialize_with = "optional_time")]
+    pub arrival_time: Option<u32>,
+    #[serde(default, deserialize_with = "optional_time")]
+    pub departure_time: Option<u32>,
+    #[serde(default, deserialize_with = "optional_time")]
+    pub start_pickup_drop_off_window: Option<u32>,
+    #[serde(default, deserialize_with = "optional_time")]
+    pub end_pickup_drop_off_window: Option<u32>,
+    pub pickup_type: Option<u8>,
+    pub drop_off_type: Option<u8>,
+    pub pickup_booking_rule_id: Option<String>,
+    pub drop_off_booking_rule_id: Option<String>,
+}
+
+impl FlexStopTime {
+    /// The stop time is at a location or a location group, and not at a stop.
+    pub fn is_flex(&self) -> bool {
+        self.location_id.is_some() || self.location_group_id.is_some()
+    }
+}
+
+/// A line of `booking_rules.txt`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct BookingRule {
+    pub booking_rule_id: String,
+    pub booking_type: u8,
+    pub prior_notice_duration_min: Option<u32>,
+    pub prior_notice_duration_max: Option<u32>,
+    pub prior_notice_last_day: Option<u32>,
+    #[serde(default, deserialize_with = "optional_time")]
+    pub prior_notice_last_time: Option<u32>,
+    pub prior_notice_start_day: Option<u32>,
+    #[serde(default, deserialize_with = "optional_time")]
+    pub prior_notice_start_time: Option<u32>,
+    pub prior_notice_service_id: Option<String>,
+}
+
+/// A line of `location_groups.txt`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct LocationGroup {
+    pub location_group_id: String,
+    pub location_group_name: Option<String>,
+}
+
+/// A line of `location_group_stops.txt`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct LocationGroupStop {
+    pub location_group_id: String,
+    pub stop_id: String,
+}
+
+fn optional_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let time = Option::<String>::deserialize(deserializer)?;
+    match time.as_deref() {
+        None | Some("") => Ok(None),
+        Some(time) => parse_time(time)
+            .map(Some)
+            .ok_or_else(|| serde::de::Error::custom(format!("invalid time `{}`", time))),
+    }
+}
+
+/// Parses a `HH:MM:SS` time, the hours being possibly given with one digit.
+pub fn parse_time(time: &str) -> Option<u32> {
+    let mut parts = time.split(':').map(|p| p.parse::<u32>().ok());
+    match (parts.next()?, parts.next()?, parts.next()?, parts.next()) {
+        (Some(h), Some(m), Some(s), None) if m < 60 && s < 60 => Some(h * 3600 + m * 60 + s),
+        _ => None,
+    }
+}
+
+#[derive(Default)]
+pub struct ExtraFiles {
+    pub transfers: Option<anyhow::Result<Vec<Transfer>>>,
+    pub levels: Option<anyhow::Result<Vec<Level>>>,
+    pub fare_leg_rules: Option<anyhow::Result<Vec<FareLegRule>>>,
+    pub fare_transfer_rules: Option<anyhow::Result<Vec<FareTransferRule>>>,
+    pub timeframes: Option<anyhow::Result<Vec<Timeframe>>>,
+    pub areas: Option<anyhow::Result<Vec<Area>>>,
+    pub stop_areas: Option<anyhow::Result<Vec<StopArea>>>,
+    pub networks: Option<anyhow::Result<Vec<Network>>>,
+    pub route_networks: Option<anyhow::Result<Vec<RouteNetwork>>>,
+    pub flex_stop_times: Option<anyhow::Result<Vec<FlexStopTime>>>,
+    pub locations: Option<anyhow::Result<geojson::FeatureCollection>>,
+    pub location_groups: Option<anyhow::Result<Vec<LocationGroup>>>,
+    pub location_group_stops: Option<anyhow::Result<Vec<LocationGroupStop>>>,
+    pub booking_rules: Option<anyhow::Result<Vec<BookingRule>>>,
+}
+
+fn error<T>(file: &Option<anyhow::Result<T>>) -> Option<&anyhow::Error> {
+    file.as_ref().and_then(|r| r.as_ref().err())
+}
+
+enum Source<R> {
+    Directory(PathBuf),
+    Archive(zip::ZipArchive<R>),
+}
+
+impl<R: Read + Seek> Source<R> {
+    // The file parsed, none if it is absent or if `parse` skips it
+    fn parse<T>(
+        &mut self,
+        file_name: &str,
+        parse: impl FnOnce(&mut dyn Read) -> anyhow::Result<Option<T>>,
+    ) -> Option<anyhow::Result<T>> {
+        let result = match self {
+            Source::Directory(dir) => {
+                let path = dir.join(file_name);
+                if !path.is_file() {
+                    return None;
+                }
+                std::fs::File::open(path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|mut file| parse(&mut file))
+            }
+            Source::Archive(archive) => {
+                // like gtfs_structures, the files are found whatever their folder
+                let index = (0..archive.len()).find(|i| {
+                    archive.name_for_index(*i).is_some_and(|name| {
+                        Path::new(name).file_name() == Some(OsStr::new(file_name))
+                    })
+                })?;
+                archive
+                    .by_index(index)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|mut file| parse(&mut file))
+            }
+        };
+        result
+            .transpose()
+            .map(|r| r.with_context(|| format!("impossible to read file '{}'", file_name)))
+    }
+
+    fn read<T: DeserializeOwned>(&mut self, file_name: &str) -> Option<anyhow::Result<Vec<T>>> {
+        self.read_with_columns(file_name, &[])
+    }
+
+    // A csv file having none of the columns is skipped
+    fn read_with_columns<T: DeserializeOwned>(
+        &mut self,
+        file_name: &str,
+        columns: &[&str],
+    ) -> Option<anyhow::Result<Vec<T>>> {
+        self.parse(file_name, |reader| {
+            let mut reader = csv::ReaderBuilder::new()
+                .flexible(true)
+                .trim(csv::Trim::Fields)
+                .from_reader(reader);
+            if !columns.is_empty()
+                && !reader
+                    .headers()?
+                    .iter()
+                    .any(|h| columns.contains(&h.trim()))
+            {
+                return Ok(None);
+            }
+            Ok(Some(reader.deserialize().collect::<Result<_, _>>()?))
+        })
+    }
+}
+
+impl ExtraFiles {
+    /// Reads the files of a directory or of a zip archive.
+    ///
+    /// Nothing is read if it can't be opened, the `RawGtfs` reporting the error.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        if path.is_dir() {
+            Self::read(Source::<std::fs::File>::Directory(path.to_owned()))
+        } else {
+            std::fs::File::open(path)
+                .map(Self::from_reader)
+                .unwrap_or_default()
+        }
+    }
+
+    /// Reads the files of a zip archive.
+    pub fn from_reader<R: Read + Seek>(reader: R) -> Self {
+        zip::ZipArchive::new(reader)
+            .map(|archive| Self::read(Source::Archive(archive)))
+            .unwrap_or_default()
+    }
+
+    fn read<R: Read + Seek>(mut source: Source<R>) -> Self {
+        ExtraFiles {
+            transfers: source.read("transfers.txt"),
+            levels: source.read("levels.txt"),
+            fare_leg_rules: source.read("fare_leg_rules.txt"),
+            fare_transfer_rules: source.read("fare_transfer_rules.txt"),
+            timeframes: source.read("timeframes.txt"),
+            areas: source.read("areas.txt"),
+            stop_areas: source.read("stop_areas.txt"),
+            networks: source.read("networks.txt"),
+            route_networks: source.read("route_networks.txt"),
+            flex_stop_times: source.read_with_columns(
+                "stop_times.txt",
+                &[
+                    "location_id",
+                    "location_group_id",
+                    "start_pickup_drop_off_window",
+                ],
+            ),
+            locations: source.parse("locations.geojson", |reader| {
+                Ok(Some(serde_json::from_reader(reader)?))
+            }),
+            location_groups: source.read("location_groups.txt"),
+            location_group_stops: source.read("location_group_stops.txt"),
+            booking_rules: source.read("booking_rules.txt"),
+        }
+    }
+
+    /// The files that could not be read.
+    ///
+    /// `transfers.txt` and `stop_times.txt` are not listed, their errors being reported when
+    /// the `RawGtfs` is read.
+    pub fn errors(&self) -> Vec<(&'static str, &anyhow::Error)> {
+        [
+            ("levels.txt", error(&self.levels)),
+            ("fare_leg_rules.txt", error(&self.fare_leg_rules)),
+            ("fare_transfer_rules.txt", error(&self.fare_transfer_rules)),
+            ("timeframes.txt", error(&self.timeframes)),
+            ("areas.txt", error(&self.areas)),
+            ("stop_areas.txt", error(&self.stop_areas)),
+            ("networks.txt", error(&self.networks)),
+            ("route_networks.txt", error(&self.route_networks)),
+            ("locations.geojson", error(&self.locations)),
+            ("location_groups.txt", error(&self.location_groups)),
+            (
+                "location_group_stops.txt",
+                error(&self.location_group_stops),
+            ),
+            ("booking_rules.txt", error(&self.booking_rules)),
+        ]
+        .into_iter()
+        .filter_map(|(file_name, error)| error.map(|e| (file_name, e)))
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::{ZipWriter, write::FileOptions};
+
+    #[test]
+    fn test_read() {
+        let transfers = ExtraFiles::from_path("test_data/transfers")
+            .transfers
+            .unwrap()
+            .unwrap();
+        assert!(
+            transfers
+                .iter()
+                .any(|t| t.from_trip_id.as_deref() == Some("AB1"))
+        );
+
+        // absent files are None
+        assert!(ExtraFiles::from_path("test_data/stops").transfers.is_none());
+
+        // stop_times.txt is only read with GTFS-Flex columns
+        assert!(
+            ExtraFiles::from_path("test_data/transfers")
+                .flex_stop_times
+                .is_none()
+        );
+        let flex_stop_times = ExtraFiles::from_path("test_data/flex")
+            .flex_stop_times
+            .unwrap()
+            .unwrap();
+        assert_eq!(8, flex_stop_times.len());
+        assert_eq!(
+            Some(8 * 3600),
+            flex_stop_times[0].start_pickup_drop_off_window
+        );
+
+        // in an archive, the files are also found in a sub folder
+        let mut data = Vec::new();
+        {
+            let mut zw = ZipWriter::new(Cursor::new(&mut data));
+            zw.start_file("gtfs/transfers.txt", FileOptions::<()>::default())
+                .unwrap();
+            zw.write_all(b"from_stop_id,to_stop_id,transfer_type\nA,B,2\n")
+                .unwrap();
+            zw.finish().unwrap();
+        }
+        let transfers = ExtraFiles::from_reader(Cursor::new(data))
+            .transfers
+            .unwrap()
+            .unwrap();
+        assert_eq!(1, transfers.len());
+        assert_eq!(Some("A".to_owned()), transfers[0].from_stop_id);
+        assert_eq!(
+            gtfs_structures::TransferType::MinTime,
+            transfers[0].transfer_type
+        );
+        assert_eq!(None, transfers[0].min_transfer_time);
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(Some(8 * 3600 + 30 * 60), parse_time("8:30:00"));
+        assert_eq!(Some(24 * 3600), parse_time("24:00:00"));
+        assert_eq!(None, parse_time("08:60:00"));
+        assert_eq!(None, parse_time("08:00"));
+        assert_eq!(None, parse_time("08:00:00:00"));
+    }
+}
@@ -2,12 +2,14 @@
 use crate::visualization;
 use geojson::FeatureCollection;
 use gtfs_structures::Gtfs;
+use schemars::JsonSchema;
 use serde::Serialize;
+use strum::EnumIter;
 
 /// Represents the severity of an [`Issue`].
 ///
 /// [`Issue`]: struct.Issue.html
-#[derive(Serialize, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
+#[derive(Serialize, JsonSchema, Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Copy, Clone)]
 pub enum Severity {
     /// Critical error, the GTFS archive couldn't be opened.
     Fatal,
@@ -20,7 +22,9 @@ pub enum Severity {
 }
 
 /// Represents the different types of issue.
-#[derive(Serialize, Debug, Eq, PartialEq, Ord, PartialOrd, Clone, Hash, Copy)]
+#[derive(
+    Serialize, JsonSchema, Debug, Eq, PartialEq, Ord, PartialOrd, Clone, Hash, Copy, EnumIter,
+)]
 pub enum IssueType {
     /// A stop is not used.
     UnusedStop,
@@ -103,15 +107,138 @@ pub enum IssueType {
     /// The GTFS is empty for both `calendar.txt` and `calendar_dates.txt`. The service
     /// is never running.
     NoCalendar,
+    /// A frequency window of a trip does not end after its start, or its headway is null.
+    InvalidFrequency,
+    /// The headway of a frequency window is implausibly short or long.
+    ImplausibleHeadway,
+    /// Some frequency windows of a trip overlap.
+    OverlappingFrequencies,
+    /// Some frequency windows of a trip are schedule-based (`exact_times` equal to `1`) and others are not.
+    InconsistentExactTimes,
+    /// A frequency-based trip is also described by several scheduled trips.
+    FrequencyAlsoScheduled,
+    /// A transfer lacks a field required by its `transfer_type`.
+    InvalidTransfer,
+    /// The walk of a transfer is implausible given the distance between its stops.
+    ImplausibleTransfer,
+    /// A transfer is given several times for the same stops, routes and trips.
+    DuplicateTransfer,
+    /// A pathway links a location that can't be linked, or has a field that is invalid or does not
+    /// match its `pathway_mode`.
+    InvalidPathway,
+    /// Following the pathways of its station, a platform can't be reached from an entrance, or
+    /// no entrance can be reached from it.
+    UnreachablePlatform,
+    /// The pathways lead to a generic node, but none leave it.
+    PathwayDeadEnd,
+    /// A file, not needed to build the model, can't be read.
+    UnreadableFile,
+    /// The amount of a fare product is not a number, or has more decimals than its currency allows.
+    InvalidAmount,
+    /// A timeframe has only one of its start and end times, or does not end after its start.
+    InvalidTimeframe,
+    /// Some timeframes of a group overlap for the same service.
+    OverlappingTimeframes,
+    /// Several leg groups have fare leg rules matching the same legs.
+    AmbiguousFareLegRules,
+    /// A fare transfer rule has inconsistent fields.
+    InvalidFareTransferRule,
+    /// Some fare rules are based on zones, but a stop has no zone.
+    MissingZoneId,
+    /// A fare is not referenced by any fare rule.
+    UnusedFare,
+    /// A location of `locations.geojson` has no id, or its geometry is not a valid polygon.
+    InvalidLocation,
+    /// A stop time at a location or a location group has inconsistent fields.
+    InvalidFlexStopTime,
+    /// The prior notice fields of a booking rule do not match its type.
+    InvalidBookingRule,
+    /// The calendar of the feed ended before the reference date.
+    ExpiredFeed,
+    /// The calendar of the feed ends a few days after the reference date.
+    FeedExpiringSoon,
+    /// The calendar of the feed starts long after the reference date.
+    FeedStartsInFuture,
+    /// A calendar starts after its end.
+    InvalidCalendar,
+    /// A service runs on no day of the week, and has no added date.
+    EmptyService,
+    /// A date is removed from a service, but is outside its calendar.
+    RemovedDateOutOfRange,
+    /// A service is not used by any trip.
+    UnusedService,
+    /// The service of a trip never runs.
+    TripNeverRuns,
+    /// The feed_start_date and feed_end_date of feed_info.txt don't match the days of service.
+    InconsistentFeedInfoDates,
+    /// No trip runs on some days of the validity period of a network or a route.
+    ServiceGap,
+    /// The number of trips of a network or a route falls well below its usual level on some days.
+    ServiceDrop,
+    /// A stop is too far from the shape of a trip serving it.
+    StopTooFarFromShape,
+    /// Along the shape of a trip, a stop comes before the stop preceding it in the trip.
+    StopOutOfOrderOnShape,
+    /// The first or last point of a shape is far from the first or last stop of a trip using it.
+    ShapeEndpointTooFarFromStop,
+    /// Several points of a shape have the same sequence.
+    DuplicateShapeSequence,
+    /// The shape_dist_traveled of a shape or of the stop times of a trip decreases.
+    DecreasingShapeDistTraveled,
+    /// The shape_dist_traveled of a stop time is beyond the end of the shape of the trip.
+    ShapeDistTraveledExceedsShape,
+    /// The shape_dist_traveled of a shape or of the stop times of a trip doesn't seem to use the same unit as the other shapes or as its shape.
+    InconsistentShapeDistTraveled,
+    /// Two consecutive points of a shape are very far apart.
+    ShapeJump,
+    /// Two trips of the same block, run by the same vehicle, overlap in time.
+    OverlappingBlockTrips,
+    /// The next trip of a block starts too far from the end of the previous one to be run by the same vehicle.
+    BlockTripsTooFar,
+    /// Several trips of a route run on the same days with the same stops and times.
+    DuplicateTrip,
+}
+
+// gtfs_structures::ObjectType does not implement JsonSchema
+fn object_type_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+    schemars::json_schema!({
+        "description": "Type of a GTFS object.",
+        "type": "string",
+        "enum": ["Agency", "Stop", "Route", "Trip", "Calendar", "Shape", "Fare", "Pathway"],
+    })
+}
+
+fn geojson_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+    schemars::json_schema!({
+        "description": "GeoJSON FeatureCollection (RFC 7946) to display the issue on a map.",
+        "type": "object",
+        "required": ["type", "features"],
+        "properties": {
+            "type": {"const": "FeatureCollection"},
+            "features": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["type", "geometry"],
+                    "properties": {
+                        "type": {"const": "Feature"},
+                        "geometry": {"type": ["object", "null"]},
+                        "properties": {"type": ["object", "null"]},
+                    },
+                },
+            },
+        },
+    })
 }
 
 /// Represents an object related to another object that is causing an issue.
-#[derive(Serialize, Debug, Eq, PartialEq)]
+#[derive(Serialize, JsonSchema, Debug, Eq, PartialEq)]
 pub struct RelatedObject {
     /// Related object's id.
     pub id: String,
     /// Related object's type.
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "object_type_schema")]
     pub object_type: Option<gtfs_structures::ObjectType>,
     /// Related object's name.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -119,7 +246,7 @@ pub struct RelatedObject {
 }
 
 /// Represent a line that is causing an issue
-#[derive(Serialize, Debug, Eq, PartialEq)]
+#[derive(Serialize, JsonSchema, Debug, Eq, PartialEq)]
 pub struct RelatedLine {
     /// line number
     pub line_number: u64,
@@ -130,7 +257,7 @@ pub struct RelatedLine {
 }
 
 /// Represent a file that is causing an issue
-#[derive(Serialize, Debug, Eq, PartialEq)]
+#[derive(Serialize, JsonSchema, Debug, Eq, PartialEq)]
 pub struct RelatedFile {
     /// File name.
     pub file_name: String,
@@ -140,7 +267,7 @@ pub struct RelatedFile {
 }
 
 /// Represents an issue.
-#[derive(Serialize, Debug, PartialEq)]
+#[derive(Serialize, JsonSchema, Debug, PartialEq)]
 pub struct Issue {
     /// Issue severity.
     pub severity: Severity,
@@ -150,6 +277,7 @@ pub struct Issue {
     pub object_id: String,
     /// Type of the object causing an issue.
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "object_type_schema")]
     pub object_type: Option<gtfs_structures::ObjectType>,
     /// Name of the object causing an issue.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -165,6 +293,7 @@ pub struct Issue {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub related_file: Option<RelatedFile>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(schema_with = "geojson_schema")]
     pub geojson: Option<FeatureCollection>,
 }
 
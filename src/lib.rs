@@ -1,11 +1,13 @@
 pub mod custom_rules;
 #[cfg(feature = "daemon")]
 pub mod daemon;
+pub mod extra_files;
 pub mod issues;
 pub mod metadatas;
+pub mod progress;
 pub mod validate;
 pub mod validators;
 pub mod visualization;
 
 pub use issues::{Issue, IssueType, RelatedObject, Severity};
-pub use validate::{validate, validate_and_metadata};
+pub use validate::{validate, validate_and_metadata, validate_and_metadata_with_extra_files};
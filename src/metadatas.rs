@@ -1,12 +1,14 @@
+use crate::extra_files::ExtraFiles;
 use crate::issues::IssueType;
 use chrono::NaiveDate;
 use gtfs_structures::{Availability, Error};
 use itertools::Itertools;
 use rgb::RGB;
+use schemars::JsonSchema;
 use serde::Serialize;
 use std::collections::HashMap;
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, JsonSchema, Debug)]
 pub struct Metadata {
     pub start_date: Option<String>,
     pub end_date: Option<String>,
@@ -20,6 +22,7 @@ pub struct Metadata {
     pub has_fares: bool,
     pub has_shapes: bool,
     pub has_pathways: bool,
+    pub has_flex: bool,
     // some stops have a pickup_type or drop_off_type equal to "ArrangeByPhone"
     pub some_stops_need_phone_agency: bool,
     // some stops have a pickup_type or drop_off_type equal to "CoordinateWithDriver"
@@ -30,7 +33,7 @@ pub struct Metadata {
     pub stats: Stats,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, JsonSchema, Debug)]
 pub struct Stats {
     pub stops_count: usize,
     pub stop_areas_count: usize,
@@ -54,14 +57,17 @@ pub struct Stats {
     pub fares_rules_count: usize,
     pub fares_products_count: usize,
     pub fares_media_count: usize,
-}
 
-pub fn extract_metadata(gtfs: &gtfs_structures::RawGtfs) -> Metadata {
-    use gtfs_structures::PickupDropOffType;
-    use gtfs_structures::RouteType::*;
+    pub flex_stop_times_count: usize,
+    pub locations_count: usize,
+    pub location_groups_count: usize,
+    pub booking_rules_count: usize,
+}
 
-    let start_end = gtfs
-        .calendar
+/// The first and last days of the calendar information, both `calendar.txt` and
+/// `calendar_dates.txt` being taken into account.
+pub fn validity_period(gtfs: &gtfs_structures::RawGtfs) -> Option<(NaiveDate, NaiveDate)> {
+    gtfs.calendar
         .as_ref()
         .and_then(|c| c.as_ref().ok())
         .unwrap_or(&vec![])
@@ -77,7 +83,14 @@ pub fn extract_metadata(gtfs: &gtfs_structures::RawGtfs) -> Metadata {
                 .map(|c| c.date),
         )
         .minmax()
-        .into_option();
+        .into_option()
+}
+
+pub fn extract_metadata(gtfs: &gtfs_structures::RawGtfs) -> Metadata {
+    use gtfs_structures::PickupDropOffType;
+    use gtfs_structures::RouteType::*;
+
+    let start_end = validity_period(gtfs);
     let format = |d: chrono::NaiveDate| d.format("%Y-%m-%d").to_string();
     let validator_version = env!("CARGO_PKG_VERSION");
     let stats = compute_stats(gtfs);
@@ -148,6 +161,7 @@ pub fn extract_metadata(gtfs: &gtfs_structures::RawGtfs) -> Metadata {
             Some(Ok(p)) => !p.is_empty(),
             _ => false,
         },
+        has_flex: false,
         some_stops_need_phone_agency: gtfs
             .stop_times
             .as_ref()
@@ -169,6 +183,34 @@ impl Metadata {
         self.stats.stops_with_wheelchair_info_count = Some(stops_with_wheelchair_info_count(gtfs));
         self.networks_start_end_dates = Some(networks_start_end_dates(self, gtfs));
     }
+
+    /// Adds the information read in the [ExtraFiles], like the GTFS-Flex services.
+    ///
+    /// [ExtraFiles]: ../extra_files/struct.ExtraFiles.html
+    pub fn enrich_with_extra_files(&mut self, extra_files: &ExtraFiles) {
+        let flex_stop_times = match &extra_files.flex_stop_times {
+            Some(Ok(stop_times)) => stop_times.iter().filter(|st| st.is_flex()).collect(),
+            _ => vec![],
+        };
+        self.stats.flex_stop_times_count = flex_stop_times.len();
+        self.stats.locations_count = match &extra_files.locations {
+            Some(Ok(locations)) => locations.features.len(),
+            _ => 0,
+        };
+        self.stats.location_groups_count = count(&extra_files.location_groups);
+        self.stats.booking_rules_count = count(&extra_files.booking_rules);
+        self.has_flex = self.stats.flex_stop_times_count > 0;
+
+        // the flex stop times are not in the RawGtfs
+        let needs = |pickup_dropoff_type: u8| {
+            flex_stop_times.iter().any(|st| {
+                st.pickup_type == Some(pickup_dropoff_type)
+                    || st.drop_off_type == Some(pickup_dropoff_type)
+            })
+        };
+        self.some_stops_need_phone_agency |= needs(2);
+        self.some_stops_need_phone_driver |= needs(3);
+    }
 }
 
 pub fn compute_stats(gtfs: &gtfs_structures::RawGtfs) -> Stats {
@@ -236,6 +278,11 @@ pub fn compute_stats(gtfs: &gtfs_structures::RawGtfs) -> Stats {
             .as_ref()
             .and_then(|r| r.as_ref().ok().map(|v| v.len()))
             .unwrap_or(0),
+
+        flex_stop_times_count: 0,
+        locations_count: 0,
+        location_groups_count: 0,
+        booking_rules_count: 0,
     }
 }
 
@@ -262,7 +309,7 @@ fn stops_with_wheelchair_info_count(gtfs: &gtfs_structures::Gtfs) -> usize {
         .count()
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, JsonSchema)]
 pub struct Interval {
     start_date: chrono::NaiveDate,
     end_date: chrono::NaiveDate,
@@ -368,6 +415,13 @@ fn has_on_demand_pickup_dropoff(
     stop_time.pickup_type == pickup_dropoff_type || stop_time.drop_off_type == pickup_dropoff_type
 }
 
+fn count<T>(objects: &Option<anyhow::Result<Vec<T>>>) -> usize {
+    objects
+        .as_ref()
+        .and_then(|r| r.as_ref().ok().map(|v| v.len()))
+        .unwrap_or(0)
+}
+
 fn counts_objects<T>(
     objects: &Result<Vec<T>, Error>,
     matches: for<'a> fn(&'a &T) -> bool,
@@ -500,6 +554,22 @@ mod tests {
         assert!(metadatas.has_pathways);
     }
 
+    #[test]
+    fn test_flex() {
+        let raw_gtfs =
+            gtfs_structures::RawGtfs::new("test_data/flex").expect("Failed to load data");
+        let mut metadatas = extract_metadata(&raw_gtfs);
+        assert!(!metadatas.has_flex);
+
+        metadatas.enrich_with_extra_files(&ExtraFiles::from_path("test_data/flex"));
+        assert!(metadatas.has_flex);
+        assert!(metadatas.some_stops_need_phone_agency);
+        assert_eq!(6, metadatas.stats.flex_stop_times_count);
+        assert_eq!(5, metadatas.stats.locations_count);
+        assert_eq!(1, metadatas.stats.location_groups_count);
+        assert_eq!(5, metadatas.stats.booking_rules_count);
+    }
+
     #[test]
     fn test_count_routes_with_custom_color() {
         let raw_gtfs = gtfs_structures::RawGtfs::new("test_data/custom_route_color")
@@ -546,7 +616,11 @@ mod tests {
   "fares_attribute_count": 2,
   "fares_rules_count": 4,
   "fares_products_count": 0,
-  "fares_media_count": 0
+  "fares_media_count": 0,
+  "flex_stop_times_count": 0,
+  "locations_count": 0,
+  "location_groups_count": 0,
+  "booking_rules_count": 0
 }"#
         );
 
@@ -573,7 +647,11 @@ mod tests {
   "fares_attribute_count": 2,
   "fares_rules_count": 4,
   "fares_products_count": 0,
-  "fares_media_count": 0
+  "fares_media_count": 0,
+  "flex_stop_times_count": 0,
+  "locations_count": 0,
+  "location_groups_count": 0,
+  "booking_rules_count": 0
 }"#
         );
     }
@@ -607,7 +685,11 @@ mod tests {
   "fares_attribute_count": 0,
   "fares_rules_count": 0,
   "fares_products_count": 0,
-  "fares_media_count": 0
+  "fares_media_count": 0,
+  "flex_stop_times_count": 0,
+  "locations_count": 0,
+  "location_groups_count": 0,
+  "booking_rules_count": 0
 }"#
         );
     }
@@ -0,0 +1,159 @@
+//! Progress of a validation, reported step by step to follow long validations.
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::time::Instant;
+
+#[derive(Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Download of the GTFS archive.
+    Download,
+    /// Reading of the files of the archive, as a whole and file by file.
+    Parsing,
+    /// Building of the model linking the objects of the files together.
+    ModelBuilding,
+    /// Run of a validator.
+    Validation,
+    /// Extraction of the metadata.
+    Metadata,
+}
+
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub stage: Stage,
+    /// The file read, or the validator run, if the step is about one of them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Duration of the step in milliseconds, only set once the step is finished.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+}
+
+/// Receives the steps of a validation, when they start and when they are finished.
+///
+/// `()` ignores them.
+pub trait Progress {
+    fn step(&self, step: Step);
+}
+
+impl Progress for () {
+    fn step(&self, _step: Step) {}
+}
+
+impl<F: Fn(Step)> Progress for F {
+    fn step(&self, step: Step) {
+        self(step)
+    }
+}
+
+/// Runs `f` as a step, reporting its start and its end.
+pub fn run<R>(
+    progress: &(impl Progress + ?Sized),
+    stage: Stage,
+    name: Option<&str>,
+    f: impl FnOnce() -> R,
+) -> R {
+    let name = name.map(str::to_owned);
+    progress.step(Step {
+        stage,
+        name: name.clone(),
+        duration_ms: None,
+    });
+    let start = Instant::now();
+    let result = f();
+    progress.step(Step {
+        stage,
+        name,
+        duration_ms: Some(start.elapsed().as_millis() as u64),
+    });
+    result
+}
+
+/// Wraps the reader of a zip archive, reporting the read of each of its files as a
+/// [Stage::Parsing] step named after the file.
+///
+/// The file being read is found from the position of the reader in the archive.
+pub struct ArchiveReader<'a, R, P: Progress> {
+    reader: R,
+    position: u64,
+    /// The files of the archive, with the range of their bytes in the archive.
+    files: Vec<(Range<u64>, String)>,
+    /// The file being read, and when its read started.
+    current: Option<(usize, Instant)>,
+    progress: &'a P,
+}
+
+impl<'a, R: Read + Seek, P: Progress> ArchiveReader<'a, R, P> {
+    pub fn new(mut reader: R, progress: &'a P) -> Self {
+        let files = zip::ZipArchive::new(&mut reader)
+            .map(|mut archive| {
+                (0..archive.len())
+                    .filter_map(|i| {
+                        let file = archive.by_index_raw(i).ok()?;
+                        let start = file.header_start();
+                        let end = file.data_start().unwrap_or(start) + file.compressed_size();
+                        Some((start..end, file.name().to_owned()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let position = reader.seek(SeekFrom::Start(0)).unwrap_or_default();
+        ArchiveReader {
+            reader,
+            position,
+            files,
+            current: None,
+            progress,
+        }
+    }
+}
+
+impl<R, P: Progress> ArchiveReader<'_, R, P> {
+    fn finish_file(&mut self) {
+        if let Some((file, start)) = self.current.take() {
+            self.progress.step(Step {
+                stage: Stage::Parsing,
+                name: Some(self.files[file].1.clone()),
+                duration_ms: Some(start.elapsed().as_millis() as u64),
+            });
+        }
+    }
+}
+
+impl<R: Read + Seek, P: Progress> Read for ArchiveReader<'_, R, P> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let file = self
+            .files
+            .iter()
+            .position(|(range, _)| range.contains(&self.position));
+        if file != self.current.map(|(current, _)| current) {
+            self.finish_file();
+            if let Some(file) = file {
+                self.progress.step(Step {
+                    stage: Stage::Parsing,
+                    name: Some(self.files[file].1.clone()),
+                    duration_ms: None,
+                });
+                self.current = Some((file, Instant::now()));
+            }
+        }
+        let read = self.reader.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek, P: Progress> Seek for ArchiveReader<'_, R, P> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.position = self.reader.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+impl<R, P: Progress> Drop for ArchiveReader<'_, R, P> {
+    fn drop(&mut self) {
+        self.finish_file();
+    }
+}
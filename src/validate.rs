@@ -1,8 +1,14 @@
+use crate::extra_files::ExtraFiles;
+use crate::progress::{self, Progress, Stage};
 use crate::{custom_rules, issues, metadatas, validators};
+use schemars::consts::meta_schemas;
+use schemars::generate::SchemaSettings;
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
 use serde::Serialize;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::convert::TryFrom;
 use std::error::Error;
+use std::io::Seek as _;
 
 fn create_unloadable_model_error(error: gtfs_structures::Error) -> issues::Issue {
     let msg = if let Some(inner) = error.source() {
@@ -38,54 +44,198 @@ fn create_unloadable_model_error(error: gtfs_structures::Error) -> issues::Issue
     issue
 }
 
-#[derive(Serialize, Debug)]
+/// Version of the [Response] format, following semantic versioning: the major version
+/// changes when the format changes in a way that can break its consumers, the minor
+/// version when fields or issue types are added.
+///
+/// [Response]: struct.Response.html
+pub const SCHEMA_VERSION: &str = "1.1.0";
+
+#[derive(Serialize, JsonSchema, Debug)]
 /// Holds the issues and metadata about the GTFS.
 pub struct Response {
+    /// Version of the format of this response.
+    pub schema_version: &'static str,
     pub metadata: Option<metadatas::Metadata>,
+    #[schemars(schema_with = "validations_schema")]
     pub validations: BTreeMap<issues::IssueType, Vec<issues::Issue>>,
 }
 
+fn validations_schema(generator: &mut SchemaGenerator) -> Schema {
+    let mut schema = json_schema!({
+        "description": "The issues, by issue type.",
+        "type": "object",
+        "additionalProperties": {
+            "type": "array",
+            "items": generator.subschema_for::<issues::Issue>(),
+        },
+    });
+    // OpenAPI 3.0 has no propertyNames, the keys are only described
+    if generator.settings().meta_schema.as_deref() == Some(meta_schemas::OPENAPI3) {
+        schema.insert(
+            "description".to_owned(),
+            "The issues, by issue type, the keys being IssueType values.".into(),
+        );
+    } else {
+        schema.insert(
+            "propertyNames".to_owned(),
+            generator.subschema_for::<issues::IssueType>().into(),
+        );
+    }
+    schema
+}
+
+/// JSON Schema of the [Response].
+///
+/// [Response]: struct.Response.html
+pub fn response_schema() -> Schema {
+    SchemaSettings::draft2020_12()
+        .for_serialize()
+        .into_generator()
+        .into_root_schema_for::<Response>()
+}
+
 /// Validates the files of the GTFS and returns its metadata and issues.
+///
+/// The [ExtraFiles] not being given, the rules needing them are not checked:
+/// use [validate_and_metadata_with_extra_files] to check them.
+///
+/// [ExtraFiles]: ../extra_files/struct.ExtraFiles.html
+/// [validate_and_metadata_with_extra_files]: fn.validate_and_metadata_with_extra_files.html
 pub fn validate_and_metadata(
     rgtfs: gtfs_structures::RawGtfs,
     max_issues: usize,
     custom_rules: &custom_rules::CustomRules,
 ) -> Response {
+    validate_and_metadata_with_extra_files(rgtfs, &ExtraFiles::default(), max_issues, custom_rules)
+}
+
+/// Same as [validate_and_metadata], also checking the [ExtraFiles] read along the GTFS.
+///
+/// [validate_and_metadata]: fn.validate_and_metadata.html
+/// [ExtraFiles]: ../extra_files/struct.ExtraFiles.html
+pub fn validate_and_metadata_with_extra_files(
+    rgtfs: gtfs_structures::RawGtfs,
+    extra_files: &ExtraFiles,
+    max_issues: usize,
+    custom_rules: &custom_rules::CustomRules,
+) -> Response {
+    validate_and_metadata_with_progress(rgtfs, extra_files, max_issues, custom_rules, &())
+}
+
+/// Same as [validate_and_metadata_with_extra_files], reporting each step of the validation to `progress`.
+///
+/// [validate_and_metadata_with_extra_files]: fn.validate_and_metadata_with_extra_files.html
+pub fn validate_and_metadata_with_progress(
+    mut rgtfs: gtfs_structures::RawGtfs,
+    extra_files: &ExtraFiles,
+    max_issues: usize,
+    custom_rules: &custom_rules::CustomRules,
+    progress: &impl Progress,
+) -> Response {
+    remove_flex_stop_times(&mut rgtfs, extra_files);
+    remove_in_seat_transfers(&mut rgtfs);
     let mut validations = BTreeMap::new();
-    let mut issues: Vec<_> = validators::raw_gtfs::validate(&rgtfs)
-        .into_iter()
-        .chain(validators::invalid_reference::validate(&rgtfs))
-        .chain(validators::file_presence::validate(&rgtfs))
-        .chain(validators::sub_folder::validate(&rgtfs))
-        .collect();
-    let mut metadata = metadatas::extract_metadata(&rgtfs);
+    let mut issues = Vec::new();
+    let mut run = |name: &str, validator: &dyn Fn() -> Vec<issues::Issue>| {
+        issues.extend(progress::run(
+            progress,
+            Stage::Validation,
+            Some(name),
+            validator,
+        ))
+    };
+    run("raw_gtfs", &|| validators::raw_gtfs::validate(&rgtfs));
+    run("invalid_reference", &|| {
+        validators::invalid_reference::validate(&rgtfs)
+    });
+    run("file_presence", &|| {
+        validators::file_presence::validate(&rgtfs)
+    });
+    run("sub_folder", &|| validators::sub_folder::validate(&rgtfs));
+    run("feed_validity", &|| {
+        validators::feed_validity::validate(&rgtfs, custom_rules)
+    });
+    run("transfers", &|| {
+        validators::transfers::validate(&rgtfs, extra_files)
+    });
+    run("pathways", &|| {
+        validators::pathways::validate(&rgtfs, extra_files)
+    });
+    run("fares_v2", &|| {
+        validators::fares_v2::validate(&rgtfs, extra_files)
+    });
+    run("flex", &|| validators::flex::validate(&rgtfs, extra_files));
+    run("extra_files", &|| {
+        validators::extra_files::validate(extra_files)
+    });
+    let mut metadata = progress::run(progress, Stage::Metadata, None, || {
+        let mut metadata = metadatas::extract_metadata(&rgtfs);
+        metadata.enrich_with_extra_files(extra_files);
+        metadata
+    });
 
-    match gtfs_structures::Gtfs::try_from(rgtfs) {
+    match progress::run(progress, Stage::ModelBuilding, None, || {
+        gtfs_structures::Gtfs::try_from(rgtfs)
+    }) {
         Ok(ref gtfs) => {
-            issues.extend(
-                validators::unused_stop::validate(gtfs)
-                    .into_iter()
-                    .chain(validators::duration_distance::validate(gtfs, custom_rules))
-                    .chain(validators::check_name::validate(gtfs))
-                    .chain(validators::check_id::validate(gtfs))
-                    .chain(validators::stops::validate(gtfs))
-                    .chain(validators::routes::validate(gtfs))
-                    .chain(validators::shapes::validate(gtfs))
-                    .chain(validators::agency::validate(gtfs))
-                    .chain(validators::calendar::validate(gtfs))
-                    .chain(validators::duplicate_stops::validate(gtfs))
-                    .chain(validators::fare_attributes::validate(gtfs))
-                    .chain(validators::feed_info::validate(gtfs))
-                    .chain(validators::stop_times::validate(gtfs))
-                    .chain(validators::interpolated_stoptimes::validate(gtfs))
-                    .chain(validators::unusable_trip::validate(gtfs)),
-            );
-            issues
-                .iter_mut()
-                .for_each(|issue| issue.push_related_geojson(gtfs));
+            let mut run = |name: &str, validator: &dyn Fn() -> Vec<issues::Issue>| {
+                issues.extend(progress::run(
+                    progress,
+                    Stage::Validation,
+                    Some(name),
+                    validator,
+                ))
+            };
+            run("unused_stop", &|| validators::unused_stop::validate(gtfs));
+            run("duration_distance", &|| {
+                validators::duration_distance::validate(gtfs, custom_rules)
+            });
+            run("check_name", &|| validators::check_name::validate(gtfs));
+            run("check_id", &|| validators::check_id::validate(gtfs));
+            run("stops", &|| validators::stops::validate(gtfs));
+            run("routes", &|| validators::routes::validate(gtfs));
+            run("shapes", &|| validators::shapes::validate(gtfs));
+            run("shape_dist_traveled", &|| {
+                validators::shape_dist_traveled::validate(gtfs)
+            });
+            run("shape_stops", &|| {
+                validators::shape_stops::validate(gtfs, custom_rules)
+            });
+            run("agency", &|| validators::agency::validate(gtfs));
+            run("calendar", &|| validators::calendar::validate(gtfs));
+            run("service_gaps", &|| {
+                validators::service_gaps::validate(gtfs, custom_rules)
+            });
+            run("duplicate_stops", &|| {
+                validators::duplicate_stops::validate(gtfs)
+            });
+            run("fare_attributes", &|| {
+                validators::fare_attributes::validate(gtfs)
+            });
+            run("fare_rules", &|| validators::fare_rules::validate(gtfs));
+            run("feed_info", &|| validators::feed_info::validate(gtfs));
+            run("stop_times", &|| validators::stop_times::validate(gtfs));
+            run("interpolated_stoptimes", &|| {
+                validators::interpolated_stoptimes::validate(gtfs)
+            });
+            run("unusable_trip", &|| {
+                validators::unusable_trip::validate(gtfs, extra_files)
+            });
+            run("frequencies", &|| validators::frequencies::validate(gtfs));
+            run("blocks", &|| validators::blocks::validate(gtfs));
+            run("duplicate_trips", &|| {
+                validators::duplicate_trips::validate(gtfs)
+            });
+
+            progress::run(progress, Stage::Metadata, Some("advanced"), || {
+                issues
+                    .iter_mut()
+                    .for_each(|issue| issue.push_related_geojson(gtfs));
 
-            // advanced_metadata::enrich_advanced_metadata(&mut metadata, gtfs);
-            metadata.enrich_with_advanced_infos(gtfs);
+                // advanced_metadata::enrich_advanced_metadata(&mut metadata, gtfs);
+                metadata.enrich_with_advanced_infos(gtfs);
+            });
         }
         Err(e) => {
             issues.push(create_unloadable_model_error(e));
@@ -105,11 +255,41 @@ pub fn validate_and_metadata(
     }
 
     Response {
+        schema_version: SCHEMA_VERSION,
         metadata: Some(metadata),
         validations,
     }
 }
 
+// The stop times at a location or a location group have no stop_id, they can't be in the model
+fn remove_flex_stop_times(rgtfs: &mut gtfs_structures::RawGtfs, extra_files: &ExtraFiles) {
+    if let (Ok(stop_times), Some(Ok(flex_stop_times))) =
+        (&mut rgtfs.stop_times, &extra_files.flex_stop_times)
+    {
+        let flex: HashSet<(&str, u32)> = flex_stop_times
+            .iter()
+            .filter(|st| st.is_flex())
+            .map(|st| (st.trip_id.as_str(), st.stop_sequence))
+            .collect();
+        stop_times.retain(|st| {
+            !(st.stop_id.is_empty() && flex.contains(&(st.trip_id.as_str(), st.stop_sequence)))
+        });
+    }
+}
+
+// The in-seat transfers can be between trips without stop, they can't be in the model
+fn remove_in_seat_transfers(rgtfs: &mut gtfs_structures::RawGtfs) {
+    use gtfs_structures::TransferType;
+    if let Some(Ok(transfers)) = &mut rgtfs.transfers {
+        transfers.retain(|t| {
+            !(matches!(
+                t.transfer_type,
+                TransferType::StayOnBoard | TransferType::MustAlight
+            ) && (t.from_stop_id.is_empty() || t.to_stop_id.is_empty()))
+        });
+    }
+}
+
 /// Returns a [Response] with every issue on the GTFS.
 ///
 /// [Response]: struct.Response.html
@@ -120,16 +300,50 @@ pub fn generate_validation(
 ) -> Response {
     log::info!("Starting validation: {}", input);
     let raw_gtfs = gtfs_structures::RawGtfs::new(input);
-    process(raw_gtfs, max_issues, custom_rules)
+    // an url is downloaded by gtfs_structures, without the extra files
+    let extra_files = ExtraFiles::from_path(input);
+    process_with_extra_files(raw_gtfs, &extra_files, max_issues, custom_rules)
 }
 
+/// Validates the GTFS if it could be read, without its [ExtraFiles].
+///
+/// [ExtraFiles]: ../extra_files/struct.ExtraFiles.html
 pub fn process(
     raw_gtfs: Result<gtfs_structures::RawGtfs, gtfs_structures::Error>,
     max_issues: usize,
     custom_rules: &custom_rules::CustomRules,
+) -> Response {
+    process_with_extra_files(raw_gtfs, &ExtraFiles::default(), max_issues, custom_rules)
+}
+
+/// Same as [process], also checking the [ExtraFiles] read along the GTFS.
+///
+/// [process]: fn.process.html
+/// [ExtraFiles]: ../extra_files/struct.ExtraFiles.html
+pub fn process_with_extra_files(
+    raw_gtfs: Result<gtfs_structures::RawGtfs, gtfs_structures::Error>,
+    extra_files: &ExtraFiles,
+    max_issues: usize,
+    custom_rules: &custom_rules::CustomRules,
+) -> Response {
+    process_with_progress(raw_gtfs, extra_files, max_issues, custom_rules, &())
+}
+
+fn process_with_progress(
+    raw_gtfs: Result<gtfs_structures::RawGtfs, gtfs_structures::Error>,
+    extra_files: &ExtraFiles,
+    max_issues: usize,
+    custom_rules: &custom_rules::CustomRules,
+    progress: &impl Progress,
 ) -> Response {
     match raw_gtfs {
-        Ok(raw_gtfs) => self::validate_and_metadata(raw_gtfs, max_issues, custom_rules),
+        Ok(raw_gtfs) => validate_and_metadata_with_progress(
+            raw_gtfs,
+            extra_files,
+            max_issues,
+            custom_rules,
+            progress,
+        ),
         Err(e) => {
             let mut validations = BTreeMap::new();
             validations.insert(
@@ -144,6 +358,7 @@ pub fn process(
                 ],
             );
             Response {
+                schema_version: SCHEMA_VERSION,
                 metadata: None,
                 validations,
             }
@@ -156,8 +371,31 @@ pub fn generate_validation_from_reader<T: std::io::Read + std::io::Seek>(
     max_issues: usize,
     custom_rules: &custom_rules::CustomRules,
 ) -> Response {
-    let g = gtfs_structures::RawGtfs::from_reader(reader);
-    process(g, max_issues, custom_rules)
+    generate_validation_from_reader_with_progress(reader, max_issues, custom_rules, &())
+}
+
+/// Same as [generate_validation_from_reader], reporting each step of the validation to `progress`.
+///
+/// The reading of the archive is a [Stage::Parsing] step, holding a step for each file read.
+///
+/// [generate_validation_from_reader]: fn.generate_validation_from_reader.html
+/// [Stage::Parsing]: ../progress/enum.Stage.html
+pub fn generate_validation_from_reader_with_progress<T: std::io::Read + std::io::Seek>(
+    reader: T,
+    max_issues: usize,
+    custom_rules: &custom_rules::CustomRules,
+    progress: &impl Progress,
+) -> Response {
+    let (g, extra_files) = progress::run(progress, Stage::Parsing, None, || {
+        let mut reader = progress::ArchiveReader::new(reader, progress);
+        let g = gtfs_structures::RawGtfs::from_reader(&mut reader);
+        let extra_files = match reader.rewind() {
+            Ok(()) => ExtraFiles::from_reader(reader),
+            Err(_) => ExtraFiles::default(),
+        };
+        (g, extra_files)
+    });
+    process_with_progress(g, &extra_files, max_issues, custom_rules, progress)
 }
 
 /// Returns a JSON with all the issues on the GTFS. Either takes an URL, a directory path or a .zip file as parameter.
@@ -229,3 +467,183 @@ fn test_invalid_stop_points() {
         }]
     );
 }
+
+#[test]
+fn test_response_schema() {
+    let schema = serde_json::to_value(response_schema()).unwrap();
+    let validator = jsonschema::validator_for(&schema).unwrap();
+    let custom_rules = custom_rules::CustomRules::default();
+    for input in [
+        "test_data/duplicate_stops",
+        "test_data/invalid_stop_file",
+        "test_data/shapes",
+        "test_data/sub_folder",
+        "test_data/does_not_exist",
+    ] {
+        let response =
+            serde_json::to_value(generate_validation(input, 1000, &custom_rules)).unwrap();
+        assert_eq!(SCHEMA_VERSION, response["schema_version"]);
+        let errors: Vec<_> = validator
+            .iter_errors(&response)
+            .map(|e| e.to_string())
+            .collect();
+        assert!(errors.is_empty(), "{}: {:?}", input, errors);
+    }
+
+    // the validations are keyed by issue type
+    let response = serde_json::json!({
+        "schema_version": SCHEMA_VERSION,
+        "metadata": null,
+        "validations": {"NotAnIssueType": []},
+    });
+    assert!(!validator.is_valid(&response));
+}
+
+// The published schema of a version must not change: a change of the output needs a new SCHEMA_VERSION
+#[test]
+fn test_pinned_schema() {
+    let path = format!("test_data/schema/response-{}.json", SCHEMA_VERSION);
+    let pinned: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(
+        pinned,
+        serde_json::to_value(response_schema()).unwrap(),
+        "the schema differs from {}, SCHEMA_VERSION must be bumped",
+        path
+    );
+}
+
+#[test]
+fn test_progress() {
+    let steps = std::cell::RefCell::new(Vec::new());
+    let raw_gtfs = gtfs_structures::RawGtfs::new("test_data/unused_stop").unwrap();
+    let progress = |step: progress::Step| steps.borrow_mut().push(step);
+    validate_and_metadata_with_progress(
+        raw_gtfs,
+        &ExtraFiles::default(),
+        1000,
+        &custom_rules::CustomRules::default(),
+        &progress,
+    );
+    let steps = steps.into_inner();
+    // each step is reported when it starts, and when it is finished with its duration
+    for pair in steps.chunks(2) {
+        assert_eq!(
+            (pair[0].stage, &pair[0].name),
+            (pair[1].stage, &pair[1].name)
+        );
+        assert!(pair[0].duration_ms.is_none() && pair[1].duration_ms.is_some());
+    }
+    assert!(steps.iter().any(|s| s.stage == Stage::ModelBuilding));
+    assert!(
+        steps
+            .iter()
+            .any(|s| s.stage == Stage::Validation && s.name.as_deref() == Some("unusable_trip"))
+    );
+}
+
+#[cfg(test)]
+fn zip_directory(dir: &str) -> std::io::Cursor<Vec<u8>> {
+    let mut data = std::io::Cursor::new(Vec::new());
+    {
+        let mut zw = zip::ZipWriter::new(&mut data);
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_str().unwrap();
+            zw.start_file(name, zip::write::FileOptions::<()>::default())
+                .unwrap();
+            std::io::Write::write_all(&mut zw, &std::fs::read(&path).unwrap()).unwrap();
+        }
+        zw.finish().unwrap();
+    }
+    data.set_position(0);
+    data
+}
+
+#[test]
+fn test_parsing_progress() {
+    let steps = std::cell::RefCell::new(Vec::new());
+    let progress = |step: progress::Step| steps.borrow_mut().push(step);
+    generate_validation_from_reader_with_progress(
+        zip_directory("test_data/unused_stop"),
+        1000,
+        &custom_rules::CustomRules::default(),
+        &progress,
+    );
+    let files: Vec<_> = steps
+        .into_inner()
+        .into_iter()
+        .filter(|s| s.stage == Stage::Parsing && s.name.is_some())
+        .collect();
+    // each file read is reported when it starts, and when it is finished with its duration
+    for pair in files.chunks(2) {
+        assert_eq!(pair[0].name, pair[1].name);
+        assert!(pair[0].duration_ms.is_none() && pair[1].duration_ms.is_some());
+    }
+    for file in ["agency.txt", "stops.txt", "stop_times.txt", "trips.txt"] {
+        assert!(files.iter().any(|s| s.name.as_deref() == Some(file)));
+    }
+}
+
+#[test]
+fn test_extra_files() {
+    let custom_rules = custom_rules::CustomRules::default();
+    let response = generate_validation("test_data/transfers", 1000, &custom_rules);
+    assert!(
+        response
+            .validations
+            .contains_key(&issues::IssueType::DuplicateTransfer)
+    );
+
+    // they are also read from a zip archive
+    let response =
+        generate_validation_from_reader(zip_directory("test_data/transfers"), 1000, &custom_rules);
+    assert!(
+        response
+            .validations
+            .contains_key(&issues::IssueType::DuplicateTransfer)
+    );
+}
+
+#[test]
+fn test_flex() {
+    let response = generate_validation(
+        "test_data/flex",
+        1000,
+        &custom_rules::CustomRules::default(),
+    );
+    // the stop times at a location, without stop_id, don't prevent to build the model
+    assert!(
+        !response
+            .validations
+            .contains_key(&issues::IssueType::UnloadableModel)
+    );
+    assert!(
+        !response.validations[&issues::IssueType::InvalidReference]
+            .iter()
+            .any(|i| i.object_id.is_empty())
+    );
+    assert!(
+        !response
+            .validations
+            .contains_key(&issues::IssueType::UnusableTrip)
+    );
+    assert!(response.metadata.unwrap().has_flex);
+}
+
+#[test]
+fn test_in_seat_transfer() {
+    let response = generate_validation(
+        "test_data/in_seat_transfer",
+        1000,
+        &custom_rules::CustomRules::default(),
+    );
+    // the in-seat transfer, without stop, doesn't prevent to build the model
+    assert!(
+        !response
+            .validations
+            .values()
+            .flatten()
+            .any(|i| i.severity == issues::Severity::Fatal)
+    );
+}
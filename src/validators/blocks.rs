@@ -0,0 +1,196 @@
+#[cfg(test)]
+use super::utils::issues_of_type;
+use super::utils::{feed_start, format_time};
+use crate::issues::{Issue, IssueType, Severity};
+use geo::{Distance as _, Haversine};
+use gtfs_structures::{Stop, Trip};
+use std::collections::{HashMap, HashSet};
+
+/// Distance in meters under which two consecutive trips of a block are always considered close enough
+const MIN_JUMP_DISTANCE: f64 = 1000.;
+/// Speed in km/h above which the vehicle can't go from the end of a trip to the start of the next one
+const MAX_JUMP_SPEED: f64 = 100.;
+
+// A trip of a block, with its first and last stops and times
+struct BlockTrip<'a> {
+    trip: &'a Trip,
+    start: u32,
+    end: u32,
+    first_stop: &'a Stop,
+    last_stop: &'a Stop,
+}
+
+impl<'a> BlockTrip<'a> {
+    fn new(trip: &'a Trip) -> Option<Self> {
+        let first = trip.stop_times.first()?;
+        let last = trip.stop_times.last()?;
+        Some(BlockTrip {
+            trip,
+            start: first.departure_time.or(first.arrival_time)?,
+            end: last.arrival_time.or(last.departure_time)?,
+            first_stop: &first.stop,
+            last_stop: &last.stop,
+        })
+    }
+}
+
+fn distance(a: &Stop, b: &Stop) -> Option<f64> {
+    Some(Haversine.distance(
+        geo::Point::new(a.longitude?, a.latitude?),
+        geo::Point::new(b.longitude?, b.latitude?),
+    ))
+}
+
+// A trip of a block on a day of service, its times counted from the start of the feed,
+// so a trip running past midnight is compared with the trips of the next day
+struct Run<'a> {
+    block_trip: &'a BlockTrip<'a>,
+    day: u16,
+    start: u64,
+    end: u64,
+}
+
+impl<'a> Run<'a> {
+    fn new(block_trip: &'a BlockTrip<'a>, day: u16) -> Self {
+        let offset = u64::from(day) * 86_400;
+        Run {
+            block_trip,
+            day,
+            start: offset + u64::from(block_trip.start),
+            end: offset + u64::from(block_trip.end),
+        }
+    }
+}
+
+fn check_consecutive_trips(previous: &Run, next: &Run) -> Option<Issue> {
+    let (previous_trip, next_trip) = (previous.block_trip, next.block_trip);
+    if next.start < previous.end {
+        let next_day = if next.day > previous.day {
+            " the next day"
+        } else {
+            ""
+        };
+        return Some(
+            Issue::new_with_obj(
+                Severity::Error,
+                IssueType::OverlappingBlockTrips,
+                previous_trip.trip,
+            )
+            .add_related_object(next_trip.trip)
+            .details(&format!(
+                "The trip ends at {}, after the start of the next trip of the block at {}{}",
+                format_time(previous_trip.end),
+                format_time(next_trip.start),
+                next_day
+            )),
+        );
+    }
+    let distance = distance(previous_trip.last_stop, next_trip.first_stop)?;
+    let duration = (next.start - previous.end) as f64;
+    if distance > MIN_JUMP_DISTANCE
+        && (duration == 0. || distance / duration * 3.6 > MAX_JUMP_SPEED)
+    {
+        return Some(
+            Issue::new_with_obj(
+                Severity::Warning,
+                IssueType::BlockTripsTooFar,
+                previous_trip.trip,
+            )
+            .add_related_object(next_trip.trip)
+            .add_related_object(previous_trip.last_stop)
+            .add_related_object(next_trip.first_stop)
+            .details(&format!(
+                "The trip ends {:.0} meters away from the start of the next trip of the block, {} seconds later",
+                distance, duration
+            )),
+        );
+    }
+    None
+}
+
+/// Check that the trips of a block, run by the same vehicle, follow each other
+pub fn validate(gtfs: &gtfs_structures::Gtfs) -> Vec<Issue> {
+    let Some(start) = feed_start(gtfs) else {
+        return vec![];
+    };
+
+    // the frequency-based trips don't have the actual times of the vehicles
+    let mut blocks: HashMap<&str, Vec<BlockTrip>> = HashMap::new();
+    for trip in gtfs.trips.values().filter(|t| t.frequencies.is_empty()) {
+        if let Some(block_id) = trip.block_id.as_deref().filter(|b| !b.is_empty())
+            && let Some(block_trip) = BlockTrip::new(trip)
+        {
+            blocks.entry(block_id).or_default().push(block_trip);
+        }
+    }
+
+    let mut service_days: HashMap<&str, Vec<u16>> = HashMap::new();
+    let mut issues = vec![];
+    for trips in blocks.values().filter(|trips| trips.len() > 1) {
+        let mut runs = vec![];
+        for block_trip in trips {
+            let service_id = block_trip.trip.service_id.as_str();
+            let trip_days = service_days
+                .entry(service_id)
+                .or_insert_with(|| gtfs.trip_days(&block_trip.trip.service_id, start));
+            runs.extend(trip_days.iter().map(|day| Run::new(block_trip, *day)));
+        }
+        runs.sort_by_key(|r| (r.start, r.end));
+
+        // each trip is checked against the one ending the latest before it, a long trip
+        // can overlap several next ones
+        // the same trips usually follow each other on many days, they are reported once
+        let mut checked = HashSet::new();
+        let mut latest: Option<&Run> = None;
+        for run in &runs {
+            if let Some(previous) = latest
+                && previous.block_trip.trip.id != run.block_trip.trip.id
+                && checked.insert((
+                    previous.block_trip.trip.id.as_str(),
+                    run.block_trip.trip.id.as_str(),
+                ))
+            {
+                issues.extend(check_consecutive_trips(previous, run));
+            }
+            if latest.is_none_or(|previous| run.end > previous.end) {
+                latest = Some(run);
+            }
+        }
+    }
+    issues
+}
+
+#[test]
+fn test_overlapping_trips() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/blocks").unwrap();
+    let issues = issues_of_type(validate(&gtfs), IssueType::OverlappingBlockTrips);
+
+    // the trips of the block B2 overlap, but don't run on the same days
+    assert_eq!(1, issues.len());
+    assert_eq!("T2", issues[0].object_id);
+    assert_eq!("T3", issues[0].related_objects[0].id);
+    assert_eq!(
+        Some(
+            "The trip ends at 09:30:00, after the start of the next trip of the block at 09:20:00"
+                .to_owned()
+        ),
+        issues[0].details
+    );
+}
+
+#[test]
+fn test_trips_too_far() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/blocks").unwrap();
+    let issues = issues_of_type(validate(&gtfs), IssueType::BlockTripsTooFar);
+
+    assert_eq!(1, issues.len());
+    assert_eq!("T1", issues[0].object_id);
+    assert_eq!(
+        vec!["T2", "B", "C"],
+        issues[0]
+            .related_objects
+            .iter()
+            .map(|o| o.id.as_str())
+            .collect::<Vec<_>>()
+    );
+}
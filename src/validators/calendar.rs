@@ -1,11 +1,185 @@
+#[cfg(test)]
+use super::utils::issues_of_type;
 use crate::issues::*;
+use chrono::NaiveDate;
+use gtfs_structures::Exception;
+use std::collections::{HashMap, HashSet};
 
 pub fn validate(gtfs: &gtfs_structures::Gtfs) -> Vec<Issue> {
     if gtfs.calendar.len() + gtfs.calendar_dates.len() == 0 {
-        vec![Issue::new(Severity::Error, IssueType::NoCalendar, "")]
-    } else {
-        vec![]
+        return vec![Issue::new(Severity::Error, IssueType::NoCalendar, "")];
     }
+    let spans = service_spans(gtfs);
+    check_calendars(gtfs)
+        .into_iter()
+        .chain(check_removed_dates(gtfs))
+        .chain(check_unused_services(gtfs))
+        .chain(check_trips(gtfs, &spans))
+        .chain(check_feed_info(gtfs, &spans))
+        .collect()
+}
+
+// The first and last days each service runs, none if it never runs
+fn service_spans(gtfs: &gtfs_structures::Gtfs) -> HashMap<&str, Option<(NaiveDate, NaiveDate)>> {
+    let services: HashSet<&str> = gtfs
+        .calendar
+        .keys()
+        .chain(gtfs.calendar_dates.keys())
+        .map(|id| id.as_str())
+        .collect();
+    services
+        .into_iter()
+        .map(|service_id| {
+            let dates = gtfs.calendar_dates.get(service_id);
+            let exceptions = |exception: Exception| {
+                dates
+                    .into_iter()
+                    .flatten()
+                    .filter(move |d| d.exception_type == exception)
+                    .map(|d| d.date)
+            };
+            let removed: HashSet<NaiveDate> = exceptions(Exception::Deleted).collect();
+            let regular_days = gtfs.calendar.get(service_id).into_iter().flat_map(|c| {
+                c.start_date
+                    .iter_days()
+                    .take_while(move |day| *day <= c.end_date)
+                    .filter(move |day| c.valid_weekday(*day))
+            });
+            let span = regular_days
+                .filter(|day| !removed.contains(day))
+                .chain(exceptions(Exception::Added))
+                .fold(None, |span: Option<(NaiveDate, NaiveDate)>, day| {
+                    Some(span.map_or((day, day), |(first, last)| (first.min(day), last.max(day))))
+                });
+            (service_id, span)
+        })
+        .collect()
+}
+
+fn check_calendars(gtfs: &gtfs_structures::Gtfs) -> Vec<Issue> {
+    gtfs.calendar
+        .values()
+        .filter_map(|c| {
+            let no_weekday = ![
+                c.monday,
+                c.tuesday,
+                c.wednesday,
+                c.thursday,
+                c.friday,
+                c.saturday,
+                c.sunday,
+            ]
+            .contains(&true);
+            let added_dates = gtfs
+                .calendar_dates
+                .get(&c.id)
+                .into_iter()
+                .flatten()
+                .any(|d| d.exception_type == Exception::Added);
+            if c.start_date > c.end_date {
+                Some(
+                    Issue::new_with_obj(Severity::Error, IssueType::InvalidCalendar, c).details(
+                        &format!(
+                            "The calendar starts on {}, after its end on {}",
+                            c.start_date, c.end_date
+                        ),
+                    ),
+                )
+            } else if no_weekday && !added_dates {
+                Some(
+                    Issue::new_with_obj(Severity::Warning, IssueType::EmptyService, c)
+                        .details("The service runs on no day of the week, and has no added date"),
+                )
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// Removing a date the calendar does not cover has no effect
+fn check_removed_dates(gtfs: &gtfs_structures::Gtfs) -> Vec<Issue> {
+    gtfs.calendar
+        .values()
+        .flat_map(|c| {
+            gtfs.calendar_dates
+                .get(&c.id)
+                .into_iter()
+                .flatten()
+                .filter(|d| d.exception_type == Exception::Deleted)
+                .filter(|d| d.date < c.start_date || d.date > c.end_date)
+                .map(move |d| {
+                    Issue::new_with_obj(Severity::Information, IssueType::RemovedDateOutOfRange, c)
+                        .details(&format!(
+                            "The date {} is removed from the service, but is not between {} and {}",
+                            d.date, c.start_date, c.end_date
+                        ))
+                })
+        })
+        .collect()
+}
+
+fn check_unused_services(gtfs: &gtfs_structures::Gtfs) -> Vec<Issue> {
+    let used: HashSet<&str> = gtfs.trips.values().map(|t| t.service_id.as_str()).collect();
+    gtfs.calendar
+        .keys()
+        .chain(gtfs.calendar_dates.keys())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .filter(|service_id| !used.contains(service_id.as_str()))
+        .map(|service_id| {
+            Issue::new(Severity::Information, IssueType::UnusedService, service_id)
+                .object_type(gtfs_structures::ObjectType::Calendar)
+                .details("The service is not used by any trip")
+        })
+        .collect()
+}
+
+fn check_trips(
+    gtfs: &gtfs_structures::Gtfs,
+    spans: &HashMap<&str, Option<(NaiveDate, NaiveDate)>>,
+) -> Vec<Issue> {
+    gtfs.trips
+        .values()
+        .filter(|t| matches!(spans.get(t.service_id.as_str()), Some(None)))
+        .map(|t| {
+            Issue::new_with_obj(Severity::Warning, IssueType::TripNeverRuns, t)
+                .details(&format!("The service {} never runs", t.service_id))
+        })
+        .collect()
+}
+
+// The period of feed_info.txt should be covered by the services
+fn check_feed_info(
+    gtfs: &gtfs_structures::Gtfs,
+    spans: &HashMap<&str, Option<(NaiveDate, NaiveDate)>>,
+) -> Vec<Issue> {
+    let first_day = spans.values().flatten().map(|(first, _)| *first).min();
+    let last_day = spans.values().flatten().map(|(_, last)| *last).max();
+    gtfs.feed_info
+        .iter()
+        .filter_map(|feed| {
+            let reason = match (feed.start_date, feed.end_date, first_day, last_day) {
+                (Some(start), Some(end), _, _) if start > end => {
+                    format!("feed_start_date {} is after feed_end_date {}", start, end)
+                }
+                (Some(start), _, Some(first), _) if start < first => format!(
+                    "feed_start_date {} is before the first day of service {}",
+                    start, first
+                ),
+                (_, Some(end), _, Some(last)) if end > last => format!(
+                    "feed_end_date {} is after the last day of service {}",
+                    end, last
+                ),
+                _ => return None,
+            };
+            Some(
+                Issue::new(Severity::Warning, IssueType::InconsistentFeedInfoDates, "")
+                    .name(&format!("{}", feed))
+                    .details(&reason),
+            )
+        })
+        .collect()
 }
 
 #[test]
@@ -32,3 +206,50 @@ fn test_calendar_is_set() {
 
     assert!(no_calendar_issues.is_empty());
 }
+
+#[test]
+fn test_calendars() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/calendar").unwrap();
+    let invalid = issues_of_type(validate(&gtfs), IssueType::InvalidCalendar);
+    assert_eq!(1, invalid.len());
+    assert_eq!("BACKWARDS", invalid[0].object_id);
+
+    let empty = issues_of_type(validate(&gtfs), IssueType::EmptyService);
+    assert_eq!(1, empty.len());
+    assert_eq!("NODAYS", empty[0].object_id);
+
+    let removed = issues_of_type(validate(&gtfs), IssueType::RemovedDateOutOfRange);
+    assert_eq!(1, removed.len());
+    assert_eq!(
+        Some(
+            "The date 2025-01-05 is removed from the service, but is not between 2024-01-01 and 2024-12-31"
+                .to_owned()
+        ),
+        removed[0].details
+    );
+}
+
+#[test]
+fn test_services() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/calendar").unwrap();
+    let unused = issues_of_type(validate(&gtfs), IssueType::UnusedService);
+    assert_eq!(1, unused.len());
+    assert_eq!("UNUSED", unused[0].object_id);
+
+    let never_runs = issues_of_type(validate(&gtfs), IssueType::TripNeverRuns);
+    assert_eq!(2, never_runs.len());
+    assert_eq!("T3", never_runs[0].object_id);
+    assert_eq!("T4", never_runs[1].object_id);
+}
+
+#[test]
+fn test_feed_info() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/calendar").unwrap();
+    let issues = issues_of_type(validate(&gtfs), IssueType::InconsistentFeedInfoDates);
+
+    assert_eq!(1, issues.len());
+    assert_eq!(
+        Some("feed_end_date 2025-12-31 is after the last day of service 2024-12-31".to_owned()),
+        issues[0].details
+    );
+}
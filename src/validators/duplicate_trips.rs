@@ -0,0 +1,132 @@
+use super::utils::feed_start;
+use crate::issues::{Issue, IssueType, Severity};
+use gtfs_structures::{ExactTimes, Trip};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// The route, the stops, the times and the frequencies of a trip; trips with the same key are identical
+type TripKey<'a> = (
+    &'a str,
+    Vec<(&'a str, Option<u32>, Option<u32>)>,
+    Vec<(u32, u32, u32, Option<ExactTimes>)>,
+);
+
+fn key(trip: &Trip) -> TripKey<'_> {
+    (
+        trip.route_id.as_str(),
+        trip.stop_times
+            .iter()
+            .map(|st| (st.stop.id.as_str(), st.arrival_time, st.departure_time))
+            .collect(),
+        trip.frequencies
+            .iter()
+            .map(|f| (f.start_time, f.end_time, f.headway_secs, f.exact_times))
+            .collect(),
+    )
+}
+
+// Splits identical trips into groups of trips all running on a same day
+//
+// A trip can be in several groups: a trip running on mondays and saturdays is grouped
+// with the one running on mondays, and with the one running on saturdays.
+fn group_by_common_days<'a>(
+    trips: &[&'a Trip],
+    days: &HashMap<&str, HashSet<u16>>,
+) -> Vec<Vec<&'a Trip>> {
+    let mut by_day: BTreeMap<u16, Vec<&Trip>> = BTreeMap::new();
+    for trip in trips {
+        for day in days.get(trip.service_id.as_str()).into_iter().flatten() {
+            by_day.entry(*day).or_default().push(trip);
+        }
+    }
+    let mut candidates: Vec<Vec<&Trip>> = by_day
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .collect();
+    // the largest groups first, so the groups included in another one can be dropped
+    candidates.sort_by_key(|group| std::cmp::Reverse(group.len()));
+    let mut groups: Vec<Vec<&Trip>> = vec![];
+    for group in candidates {
+        if !groups.iter().any(|larger| {
+            group
+                .iter()
+                .all(|t| larger.iter().any(|l| std::ptr::eq(*l, *t)))
+        }) {
+            groups.push(group);
+        }
+    }
+    groups
+}
+
+/// Check that a trip is not given several times, with different ids
+pub fn validate(gtfs: &gtfs_structures::Gtfs) -> Vec<Issue> {
+    let Some(start) = feed_start(gtfs) else {
+        return vec![];
+    };
+
+    // a frequency-based trip is only identical to another one with the same frequencies,
+    // the scheduled trips running during its windows are reported by the frequencies validator
+    let mut identical_trips: HashMap<TripKey, Vec<&Trip>> = HashMap::new();
+    for trip in gtfs.trips.values().filter(|t| !t.stop_times.is_empty()) {
+        identical_trips.entry(key(trip)).or_default().push(trip);
+    }
+
+    let mut days: HashMap<&str, HashSet<u16>> = HashMap::new();
+    let mut issues = vec![];
+    for trips in identical_trips.values().filter(|trips| trips.len() > 1) {
+        for trip in trips.iter() {
+            days.entry(trip.service_id.as_str()).or_insert_with(|| {
+                gtfs.trip_days(&trip.service_id, start)
+                    .into_iter()
+                    .collect()
+            });
+        }
+        for mut group in group_by_common_days(trips, &days) {
+            group.sort_by(|a, b| a.id.cmp(&b.id));
+            let mut issue =
+                Issue::new_with_obj(Severity::Warning, IssueType::DuplicateTrip, group[0]).details(
+                    &format!(
+                        "{} trips run on common days with the same stops and times",
+                        group.len()
+                    ),
+                );
+            for trip in &group[1..] {
+                issue.push_related_object(*trip);
+            }
+            issues.push(issue);
+        }
+    }
+    issues
+}
+
+#[test]
+fn test() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/duplicate_trips").unwrap();
+    let mut issues = validate(&gtfs);
+    issues.sort_by(|a, b| a.object_id.cmp(&b.object_id));
+
+    let related = |issue: &Issue| {
+        issue
+            .related_objects
+            .iter()
+            .map(|o| o.id.clone())
+            .collect::<Vec<_>>()
+    };
+
+    // T4 runs on other days, T5 on another route, T6 at other times
+    // F3 runs at other frequencies than F1 and F2
+    assert_eq!(4, issues.len());
+    assert_eq!("F1", issues[0].object_id);
+    assert_eq!(vec!["F2"], related(&issues[0]));
+    assert_eq!("T1", issues[1].object_id);
+    assert_eq!(IssueType::DuplicateTrip, issues[1].issue_type);
+    assert_eq!(vec!["T2", "T3"], related(&issues[1]));
+    assert_eq!(
+        Some("3 trips run on common days with the same stops and times".to_owned()),
+        issues[1].details
+    );
+    // TA runs on mondays, TB on mondays and saturdays, TC on saturdays: TA and TC are not duplicates
+    assert_eq!("TA", issues[2].object_id);
+    assert_eq!(vec!["TB"], related(&issues[2]));
+    assert_eq!("TB", issues[3].object_id);
+    assert_eq!(vec!["TC"], related(&issues[3]));
+}
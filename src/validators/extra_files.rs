@@ -0,0 +1,33 @@
+use crate::extra_files::ExtraFiles;
+use crate::issues::{Issue, IssueType, Severity};
+
+/// Check that the files not read by `gtfs_structures` could be read
+pub fn validate(extra_files: &ExtraFiles) -> Vec<Issue> {
+    extra_files
+        .errors()
+        .into_iter()
+        .map(|(file_name, error)| {
+            Issue::new(Severity::Error, IssueType::UnreadableFile, file_name)
+                .details(&format!("{:#}", error))
+        })
+        .collect()
+}
+
+#[test]
+fn test() {
+    let extra_files = ExtraFiles {
+        levels: Some(Err(anyhow::anyhow!("missing field `level_index`"))),
+        ..Default::default()
+    };
+    let issues = validate(&extra_files);
+
+    assert_eq!(1, issues.len());
+    assert_eq!(IssueType::UnreadableFile, issues[0].issue_type);
+    assert_eq!("levels.txt", issues[0].object_id);
+    assert_eq!(
+        Some("missing field `level_index`".to_owned()),
+        issues[0].details
+    );
+
+    assert!(validate(&ExtraFiles::from_path("test_data/pathways")).is_empty());
+}
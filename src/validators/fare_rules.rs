@@ -0,0 +1,157 @@
+#[cfg(test)]
+use super::utils::issues_of_type;
+use crate::issues::{Issue, IssueType, Severity};
+use gtfs_structures::{FareRule, LocationType, ObjectType};
+use std::collections::{BTreeMap, HashSet};
+
+fn zones(rule: &FareRule) -> impl Iterator<Item = &String> {
+    [&rule.origin_id, &rule.destination_id, &rule.contains_id]
+        .into_iter()
+        .flatten()
+        .filter(|zone| !zone.is_empty())
+}
+
+fn check_references(gtfs: &gtfs_structures::Gtfs) -> Vec<Issue> {
+    let zones_of_stops: HashSet<&str> = gtfs
+        .stops
+        .values()
+        .filter_map(|s| s.zone_id.as_deref())
+        .collect();
+    // sorted by object, for the issues to always be in the same order
+    let mut issues = BTreeMap::new();
+    for rule in gtfs.fare_rules.values().flatten() {
+        if !gtfs.fare_attributes.contains_key(&rule.fare_id) {
+            issues
+                .entry((rule.fare_id.as_str(), "fare"))
+                .or_insert_with(|| {
+                    Issue::new(Severity::Fatal, IssueType::InvalidReference, &rule.fare_id)
+                        .object_type(ObjectType::Fare)
+                        .details("The fare is referenced by a fare rule but does not exist")
+                });
+        }
+        if let Some(route_id) = rule.route_id.as_deref().filter(|id| !id.is_empty())
+            && !gtfs.routes.contains_key(route_id)
+        {
+            issues.entry((route_id, "route")).or_insert_with(|| {
+                Issue::new(Severity::Fatal, IssueType::InvalidReference, route_id)
+                    .object_type(ObjectType::Route)
+                    .details("The route is referenced by a fare rule but does not exist")
+            });
+        }
+        for zone in zones(rule).filter(|zone| !zones_of_stops.contains(zone.as_str())) {
+            issues.entry((zone.as_str(), "zone")).or_insert_with(|| {
+                Issue::new(Severity::Fatal, IssueType::InvalidReference, zone)
+                    .details("The zone is referenced by a fare rule but no stop belongs to it")
+            });
+        }
+    }
+    issues.into_values().collect()
+}
+
+// With zone based fare rules, the fare of a trip can't be known if one of its stops has no zone
+fn check_stop_zones(gtfs: &gtfs_structures::Gtfs) -> Vec<Issue> {
+    if !gtfs
+        .fare_rules
+        .values()
+        .flatten()
+        .any(|r| zones(r).next().is_some())
+    {
+        return vec![];
+    }
+    gtfs.stops
+        .values()
+        .filter(|s| s.location_type == LocationType::StopPoint)
+        .filter(|s| s.zone_id.as_deref().unwrap_or_default().is_empty())
+        .map(|s| {
+            Issue::new_with_obj(Severity::Warning, IssueType::MissingZoneId, &**s)
+                .details("Some fare rules are based on zones, but the stop has no zone_id")
+        })
+        .collect()
+}
+
+// Without any fare rule, the fares apply to the whole network
+fn check_unused_fares(gtfs: &gtfs_structures::Gtfs) -> Vec<Issue> {
+    if gtfs.fare_rules.is_empty() {
+        return vec![];
+    }
+    gtfs.fare_attributes
+        .keys()
+        .filter(|fare_id| !gtfs.fare_rules.contains_key(*fare_id))
+        .map(|fare_id| {
+            Issue::new(Severity::Information, IssueType::UnusedFare, fare_id)
+                .object_type(ObjectType::Fare)
+                .details("The fare is not referenced by any fare rule")
+        })
+        .collect()
+}
+
+/// Check the fare rules, linking the fares to the routes and the zones of the stops
+pub fn validate(gtfs: &gtfs_structures::Gtfs) -> Vec<Issue> {
+    check_references(gtfs)
+        .into_iter()
+        .chain(check_stop_zones(gtfs))
+        .chain(check_unused_fares(gtfs))
+        .collect()
+}
+
+// The issues of the given type found in the test data
+#[cfg(test)]
+fn issues(issue_type: IssueType) -> Vec<Issue> {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/fare_rules").unwrap();
+    issues_of_type(validate(&gtfs), issue_type)
+}
+
+#[test]
+fn test_references() {
+    let issues = issues(IssueType::InvalidReference)
+        .into_iter()
+        .map(|i| (i.object_id, i.details.unwrap()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        vec![
+            (
+                "other_zone".to_owned(),
+                "The zone is referenced by a fare rule but no stop belongs to it".to_owned()
+            ),
+            (
+                "unknown_fare".to_owned(),
+                "The fare is referenced by a fare rule but does not exist".to_owned()
+            ),
+            (
+                "unknown_route".to_owned(),
+                "The route is referenced by a fare rule but does not exist".to_owned()
+            ),
+            (
+                "unknown_zone".to_owned(),
+                "The zone is referenced by a fare rule but no stop belongs to it".to_owned()
+            ),
+        ],
+        issues
+    );
+}
+
+#[test]
+fn test_missing_zone_id() {
+    let issues = issues(IssueType::MissingZoneId);
+
+    assert_eq!(1, issues.len());
+    assert_eq!("FAR", issues[0].object_id);
+    assert_eq!(Some(ObjectType::Stop), issues[0].object_type);
+}
+
+#[test]
+fn test_unused_fare() {
+    let issues = issues(IssueType::UnusedFare);
+
+    assert_eq!(1, issues.len());
+    assert_eq!("unused", issues[0].object_id);
+
+    // without fare rules, the fares are not expected to be referenced
+    let gtfs = gtfs_structures::Gtfs::new("test_data/fare_attributes").unwrap();
+    assert!(
+        !validate(&gtfs)
+            .iter()
+            .any(|i| i.issue_type == IssueType::UnusedFare)
+    );
+}
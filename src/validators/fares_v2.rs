@@ -0,0 +1,537 @@
+use super::utils::dedup_by_object;
+#[cfg(test)]
+use super::utils::issues_of_type;
+use crate::extra_files::{ExtraFiles, FareTransferRule};
+use crate::issues::{Issue, IssueType, Severity};
+use gtfs_structures::ObjectType;
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const END_OF_DAY: u32 = 24 * 3600;
+
+/// The kinds of objects referenced by the fares.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    FareProduct,
+    FareMedia,
+    RiderCategory,
+    Area,
+    TimeframeGroup,
+    LegGroup,
+    Network,
+    Stop,
+    Route,
+    Service,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::FareProduct => "fare product",
+            Kind::FareMedia => "fare media",
+            Kind::RiderCategory => "rider category",
+            Kind::Area => "area",
+            Kind::TimeframeGroup => "timeframe group",
+            Kind::LegGroup => "leg group",
+            Kind::Network => "network",
+            Kind::Stop => "stop",
+            Kind::Route => "route",
+            Kind::Service => "service",
+        }
+    }
+
+    fn object_type(self) -> Option<ObjectType> {
+        match self {
+            Kind::FareProduct => Some(ObjectType::Fare),
+            Kind::Stop => Some(ObjectType::Stop),
+            Kind::Route => Some(ObjectType::Route),
+            Kind::Service => Some(ObjectType::Calendar),
+            Kind::FareMedia
+            | Kind::RiderCategory
+            | Kind::Area
+            | Kind::TimeframeGroup
+            | Kind::LegGroup
+            | Kind::Network => None,
+        }
+    }
+}
+
+/// The ids of the objects that can be referenced, by kind of object.
+///
+/// A kind is missing when its file could not be read, its references not being checked.
+struct Ids<'a> {
+    raw_gtfs: &'a gtfs_structures::RawGtfs,
+    known: HashMap<Kind, HashSet<&'a str>>,
+}
+
+impl<'a> Ids<'a> {
+    fn new(raw_gtfs: &'a gtfs_structures::RawGtfs, extra_files: &'a ExtraFiles) -> Self {
+        let mut ids = Ids {
+            raw_gtfs,
+            known: HashMap::new(),
+        };
+        ids.insert_optional(
+            Kind::FareProduct,
+            "fare_products.txt",
+            &raw_gtfs.fare_products,
+            |p| &p.id,
+        );
+        ids.insert_optional(
+            Kind::FareMedia,
+            "fare_media.txt",
+            &raw_gtfs.fare_media,
+            |m| &m.id,
+        );
+        ids.insert_optional(
+            Kind::RiderCategory,
+            "rider_categories.txt",
+            &raw_gtfs.rider_categories,
+            |c| &c.id,
+        );
+        ids.insert_optional(Kind::Area, "areas.txt", &extra_files.areas, |a| &a.area_id);
+        ids.insert_optional(
+            Kind::TimeframeGroup,
+            "timeframes.txt",
+            &extra_files.timeframes,
+            |t| &t.timeframe_group_id,
+        );
+        ids.insert_optional(
+            Kind::LegGroup,
+            "fare_leg_rules.txt",
+            &extra_files.fare_leg_rules,
+            |r| r.leg_group_id.as_deref().unwrap_or_default(),
+        );
+        // networks can also be given in routes.txt, that is not read
+        if extra_files.networks.is_some() || extra_files.route_networks.is_some() {
+            ids.insert_optional(Kind::Network, "networks.txt", &extra_files.networks, |n| {
+                &n.network_id
+            });
+            if let (Some(networks), Some(Ok(route_networks))) = (
+                ids.known.get_mut(&Kind::Network),
+                &extra_files.route_networks,
+            ) {
+                networks.extend(route_networks.iter().map(|r| r.network_id.as_str()));
+            }
+        }
+        if let Ok(stops) = &raw_gtfs.stops {
+            ids.known
+                .insert(Kind::Stop, stops.iter().map(|s| s.id.as_str()).collect());
+        }
+        if let Ok(routes) = &raw_gtfs.routes {
+            ids.known
+                .insert(Kind::Route, routes.iter().map(|r| r.id.as_str()).collect());
+        }
+        let services = [
+            raw_gtfs.calendar.as_ref().map(|c| {
+                c.as_ref()
+                    .map(|c| c.iter().map(|c| c.id.as_str()).collect::<Vec<_>>())
+            }),
+            raw_gtfs.calendar_dates.as_ref().map(|c| {
+                c.as_ref()
+                    .map(|c| c.iter().map(|c| c.service_id.as_str()).collect::<Vec<_>>())
+            }),
+        ];
+        if services.iter().all(|s| !matches!(s, Some(Err(_)))) {
+            ids.known.insert(
+                Kind::Service,
+                services.into_iter().flatten().flatten().flatten().collect(),
+            );
+        }
+        ids
+    }
+
+    // The ids of an optional file, none if it is absent
+    fn insert_optional<T, E>(
+        &mut self,
+        kind: Kind,
+        file_name: &str,
+        file: &'a Option<Result<Vec<T>, E>>,
+        id: impl Fn(&'a T) -> &'a str,
+    ) {
+        let ids = match file {
+            Some(Ok(objects)) => objects.iter().map(id).collect(),
+            Some(Err(_)) => return,
+            // when the ExtraFiles have not been read, their files are still listed
+            None if self.raw_gtfs.files.iter().any(|f| f.ends_with(file_name)) => return,
+            None => HashSet::new(),
+        };
+        self.known.insert(kind, ids);
+    }
+
+    fn check_ref(&self, kind: Kind, id: &Option<String>, referenced_by: &str) -> Option<Issue> {
+        let id = id.as_deref().filter(|id| !id.is_empty())?;
+        if self.known.get(&kind)?.contains(id) {
+            return None;
+        }
+        let issue = Issue::new(Severity::Fatal, IssueType::InvalidReference, id).details(&format!(
+            "The {} is referenced by {} but does not exist",
+            kind.name(),
+            referenced_by
+        ));
+        Some(match kind.object_type() {
+            Some(object_type) => issue.object_type(object_type),
+            None => issue,
+        })
+    }
+}
+
+fn some(id: &str) -> Option<String> {
+    Some(id.to_owned())
+}
+
+fn check_references(
+    raw_gtfs: &gtfs_structures::RawGtfs,
+    extra_files: &ExtraFiles,
+    ids: &Ids,
+) -> Vec<Issue> {
+    let mut issues = vec![];
+    if let Some(Ok(products)) = &raw_gtfs.fare_products {
+        for p in products {
+            issues.push(ids.check_ref(Kind::FareMedia, &p.fare_media_id, "a fare product"));
+            issues.push(ids.check_ref(Kind::RiderCategory, &p.rider_category_id, "a fare product"));
+        }
+    }
+    if let Some(Ok(rules)) = &extra_files.fare_leg_rules {
+        for r in rules {
+            let by = "a fare leg rule";
+            issues.push(ids.check_ref(Kind::FareProduct, &some(&r.fare_product_id), by));
+            issues.push(ids.check_ref(Kind::Network, &r.network_id, by));
+            issues.push(ids.check_ref(Kind::Area, &r.from_area_id, by));
+            issues.push(ids.check_ref(Kind::Area, &r.to_area_id, by));
+            issues.push(ids.check_ref(Kind::TimeframeGroup, &r.from_timeframe_group_id, by));
+            issues.push(ids.check_ref(Kind::TimeframeGroup, &r.to_timeframe_group_id, by));
+        }
+    }
+    if let Some(Ok(rules)) = &extra_files.fare_transfer_rules {
+        for r in rules {
+            let by = "a fare transfer rule";
+            issues.push(ids.check_ref(Kind::FareProduct, &r.fare_product_id, by));
+            issues.push(ids.check_ref(Kind::LegGroup, &r.from_leg_group_id, by));
+            issues.push(ids.check_ref(Kind::LegGroup, &r.to_leg_group_id, by));
+        }
+    }
+    if let Some(Ok(stop_areas)) = &extra_files.stop_areas {
+        for s in stop_areas {
+            issues.push(ids.check_ref(Kind::Area, &some(&s.area_id), "a stop area"));
+            issues.push(ids.check_ref(Kind::Stop, &some(&s.stop_id), "a stop area"));
+        }
+    }
+    if let Some(Ok(route_networks)) = &extra_files.route_networks {
+        for r in route_networks {
+            issues.push(ids.check_ref(Kind::Route, &some(&r.route_id), "a route network"));
+        }
+    }
+    if let Some(Ok(timeframes)) = &extra_files.timeframes {
+        for t in timeframes {
+            issues.push(ids.check_ref(Kind::Service, &some(&t.service_id), "a timeframe"));
+        }
+    }
+    dedup_by_object(issues.into_iter().flatten())
+}
+
+fn check_products(raw_gtfs: &gtfs_structures::RawGtfs) -> Vec<Issue> {
+    let products = match &raw_gtfs.fare_products {
+        Some(Ok(products)) => products,
+        _ => return vec![],
+    };
+    products
+        .iter()
+        .filter_map(|p| {
+            let issue = |issue_type, details: &str| {
+                Some(
+                    Issue::new(Severity::Error, issue_type, &p.id)
+                        .object_type(ObjectType::Fare)
+                        .details(details),
+                )
+            };
+            let currency = iso4217::alpha3(&p.currency);
+            let decimals = p.amount.split_once('.').map_or(0, |(_, d)| d.len());
+            if p.amount.is_empty() {
+                issue(IssueType::MissingPrice, "The fare product has no amount")
+            } else if currency.is_none() {
+                issue(
+                    IssueType::InvalidCurrency,
+                    &format!("{} is not a valid currency", p.currency),
+                )
+            } else if p.amount.parse::<f64>().is_err() {
+                issue(
+                    IssueType::InvalidAmount,
+                    &format!("{} is not a valid amount", p.amount),
+                )
+            } else if let Some(currency) = currency
+                && currency.exp >= 0
+                && decimals > currency.exp as usize
+            {
+                issue(
+                    IssueType::InvalidAmount,
+                    &format!(
+                        "{} has more decimals than the {} allows",
+                        p.amount, currency.alpha3
+                    ),
+                )
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn check_timeframes(extra_files: &ExtraFiles) -> Vec<Issue> {
+    let timeframes = match &extra_files.timeframes {
+        Some(Ok(timeframes)) => timeframes,
+        _ => return vec![],
+    };
+    let mut issues = vec![];
+    let mut intervals: BTreeMap<(&str, &str), Vec<(u32, u32)>> = BTreeMap::new();
+    for t in timeframes {
+        let invalid = |details: &str| {
+            Issue::new(
+                Severity::Error,
+                IssueType::InvalidTimeframe,
+                &t.timeframe_group_id,
+            )
+            .details(details)
+        };
+        match (t.start_time, t.end_time) {
+            (Some(_), None) | (None, Some(_)) => issues.push(invalid(
+                "start_time and end_time must be both given, or both left empty",
+            )),
+            (Some(start), Some(end)) if start >= end => {
+                issues.push(invalid("end_time must be after start_time"))
+            }
+            (_, Some(end)) if end > END_OF_DAY => {
+                issues.push(invalid("end_time must not be after 24:00:00"))
+            }
+            (start, end) => intervals
+                .entry((&t.timeframe_group_id, &t.service_id))
+                .or_default()
+                .push((start.unwrap_or(0), end.unwrap_or(END_OF_DAY))),
+        }
+    }
+    for ((group, service), intervals) in intervals {
+        if intervals
+            .into_iter()
+            .sorted()
+            .tuple_windows()
+            .any(|(i1, i2)| i2.0 < i1.1)
+        {
+            issues.push(
+                Issue::new(Severity::Error, IssueType::OverlappingTimeframes, group).details(
+                    &format!(
+                        "Some timeframes of the group overlap for the service {}",
+                        service
+                    ),
+                ),
+            );
+        }
+    }
+    issues
+}
+
+// Legs matching the same rules should belong to the same leg group
+fn check_leg_rules(extra_files: &ExtraFiles) -> Vec<Issue> {
+    let rules = match &extra_files.fare_leg_rules {
+        Some(Ok(rules)) => rules,
+        _ => return vec![],
+    };
+    rules
+        .iter()
+        .into_group_map_by(|r| {
+            (
+                &r.network_id,
+                &r.from_area_id,
+                &r.to_area_id,
+                &r.from_timeframe_group_id,
+                &r.to_timeframe_group_id,
+                r.rule_priority,
+            )
+        })
+        .into_values()
+        .filter_map(|rules| {
+            let groups = rules
+                .iter()
+                .map(|r| r.leg_group_id.as_deref().unwrap_or_default())
+                .unique()
+                .sorted()
+                .collect::<Vec<_>>();
+            if groups.len() < 2 {
+                return None;
+            }
+            Some(
+                Issue::new(Severity::Warning, IssueType::AmbiguousFareLegRules, groups[0])
+                    .details(&format!(
+                        "The leg groups {} have rules with the same network, areas, timeframes and priority",
+                        groups.join(", ")
+                    )),
+            )
+        })
+        // the rules are grouped in a HashMap, the issues are sorted to always be in the same order
+        .sorted_by(|a, b| (&a.object_id, &a.details).cmp(&(&b.object_id, &b.details)))
+        .collect()
+}
+
+fn check_transfer_rule(r: &FareTransferRule) -> Option<&'static str> {
+    if r.duration_limit.is_some() != r.duration_limit_type.is_some() {
+        Some("duration_limit_type must be given if and only if duration_limit is")
+    } else if r.duration_limit_type.is_some_and(|t| t > 3) {
+        Some("duration_limit_type must be 0, 1, 2 or 3")
+    } else if r.fare_transfer_type > 2 {
+        Some("fare_transfer_type must be 0, 1 or 2")
+    } else if r.duration_limit == Some(0) {
+        Some("duration_limit must be positive")
+    } else if r.from_leg_group_id != r.to_leg_group_id && r.transfer_count.is_some() {
+        Some("transfer_count is only for transfers between legs of the same group")
+    } else if r.from_leg_group_id == r.to_leg_group_id && r.transfer_count.is_none() {
+        Some("transfer_count is required for transfers between legs of the same group")
+    } else if r.transfer_count.is_some_and(|c| c == 0 || c < -1) {
+        Some("transfer_count must be -1, for no limit, or positive")
+    } else {
+        None
+    }
+}
+
+fn check_transfer_rules(extra_files: &ExtraFiles) -> Vec<Issue> {
+    let rules = match &extra_files.fare_transfer_rules {
+        Some(Ok(rules)) => rules,
+        _ => return vec![],
+    };
+    rules
+        .iter()
+        .filter_map(|r| {
+            check_transfer_rule(r).map(|reason| {
+                let from = r.from_leg_group_id.as_deref().unwrap_or_default();
+                let to = r.to_leg_group_id.as_deref().unwrap_or_default();
+                Issue::new(Severity::Error, IssueType::InvalidFareTransferRule, from).details(
+                    &format!(
+                        "The transfer rule from the leg group {} to {}: {}",
+                        from, to, reason
+                    ),
+                )
+            })
+        })
+        .collect()
+}
+
+/// Check the fares v2 files: their references and the consistency of their rules
+pub fn validate(raw_gtfs: &gtfs_structures::RawGtfs, extra_files: &ExtraFiles) -> Vec<Issue> {
+    let ids = Ids::new(raw_gtfs, extra_files);
+    check_references(raw_gtfs, extra_files, &ids)
+        .into_iter()
+        .chain(check_products(raw_gtfs))
+        .chain(check_timeframes(extra_files))
+        .chain(check_leg_rules(extra_files))
+        .chain(check_transfer_rules(extra_files))
+        .collect()
+}
+
+// The issues of the given type found in the test data
+#[cfg(test)]
+fn issues(issue_type: IssueType) -> Vec<Issue> {
+    let raw_gtfs = gtfs_structures::RawGtfs::new("test_data/fares_v2").unwrap();
+    let extra_files = ExtraFiles::from_path("test_data/fares_v2");
+    issues_of_type(validate(&raw_gtfs, &extra_files), issue_type)
+}
+
+#[test]
+fn test_references() {
+    let issues = issues(IssueType::InvalidReference)
+        .into_iter()
+        .map(|i| (i.object_id, i.details.unwrap()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        vec![
+            (
+                "unknown_area".to_owned(),
+                "The area is referenced by a fare leg rule but does not exist".to_owned()
+            ),
+            (
+                "unknown_category".to_owned(),
+                "The rider category is referenced by a fare product but does not exist".to_owned()
+            ),
+            (
+                "unknown_group".to_owned(),
+                "The leg group is referenced by a fare transfer rule but does not exist".to_owned()
+            ),
+            (
+                "unknown_network".to_owned(),
+                "The network is referenced by a fare leg rule but does not exist".to_owned()
+            ),
+            (
+                "unknown_product".to_owned(),
+                "The fare product is referenced by a fare leg rule but does not exist".to_owned()
+            ),
+            (
+                "unknown_service".to_owned(),
+                "The service is referenced by a timeframe but does not exist".to_owned()
+            ),
+            (
+                "unknown_stop".to_owned(),
+                "The stop is referenced by a stop area but does not exist".to_owned()
+            ),
+            (
+                "unknown_timeframe".to_owned(),
+                "The timeframe group is referenced by a fare leg rule but does not exist"
+                    .to_owned()
+            ),
+        ],
+        issues
+    );
+}
+
+#[test]
+fn test_products() {
+    let currencies = issues(IssueType::InvalidCurrency);
+    assert_eq!(1, currencies.len());
+    assert_eq!("bad_currency", currencies[0].object_id);
+
+    let amounts = issues(IssueType::InvalidAmount);
+    assert_eq!(2, amounts.len());
+    assert_eq!("bad_amount", amounts[0].object_id);
+    assert_eq!(
+        Some("1.505 has more decimals than the EUR allows".to_owned()),
+        amounts[1].details
+    );
+}
+
+#[test]
+fn test_timeframes() {
+    let invalid = issues(IssueType::InvalidTimeframe);
+    assert_eq!(2, invalid.len());
+    assert_eq!(
+        Some("end_time must be after start_time".to_owned()),
+        invalid[0].details
+    );
+    assert_eq!(
+        Some("start_time and end_time must be both given, or both left empty".to_owned()),
+        invalid[1].details
+    );
+
+    let overlapping = issues(IssueType::OverlappingTimeframes);
+    assert_eq!(1, overlapping.len());
+    assert_eq!("peak", overlapping[0].object_id);
+}
+
+#[test]
+fn test_leg_rules() {
+    let ambiguous = issues(IssueType::AmbiguousFareLegRules);
+    assert_eq!(1, ambiguous.len());
+    assert_eq!(
+        Some("The leg groups bus, bus_again have rules with the same network, areas, timeframes and priority".to_owned()),
+        ambiguous[0].details
+    );
+}
+
+#[test]
+fn test_transfer_rules() {
+    let invalid = issues(IssueType::InvalidFareTransferRule);
+    assert_eq!(2, invalid.len());
+    assert_eq!(
+        Some("The transfer rule from the leg group bus to bus: transfer_count is required for transfers between legs of the same group".to_owned()),
+        invalid[0].details
+    );
+    assert_eq!(
+        Some("The transfer rule from the leg group bus to metro: duration_limit_type must be given if and only if duration_limit is".to_owned()),
+        invalid[1].details
+    );
+}
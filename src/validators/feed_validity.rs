@@ -0,0 +1,158 @@
+use crate::custom_rules::CustomRules;
+use crate::issues::{Issue, IssueType, Severity};
+use crate::metadatas;
+use chrono::NaiveDate;
+use gtfs_structures::ObjectType;
+
+/// Number of days before its end a feed is reported as expiring soon, if not customized
+const EXPIRATION_WARNING_DAYS: u32 = 7;
+/// Number of days before its start a feed is reported as starting in the future, if not customized
+const MAX_DAYS_BEFORE_START: u32 = 30;
+
+// The service running on the date, a bound of the validity period of the feed
+fn service_on(raw_gtfs: &gtfs_structures::RawGtfs, date: NaiveDate) -> &str {
+    let calendar = raw_gtfs.calendar.as_ref().and_then(|c| c.as_ref().ok());
+    let calendar_dates = raw_gtfs
+        .calendar_dates
+        .as_ref()
+        .and_then(|c| c.as_ref().ok());
+    calendar
+        .into_iter()
+        .flatten()
+        .find(|c| c.start_date == date || c.end_date == date)
+        .map(|c| c.id.as_str())
+        .or_else(|| {
+            calendar_dates
+                .into_iter()
+                .flatten()
+                .find(|cd| {
+                    cd.date == date && cd.exception_type == gtfs_structures::Exception::Added
+                })
+                .map(|cd| cd.service_id.as_str())
+        })
+        .unwrap_or_default()
+}
+
+/// Check the validity period of the feed against the reference date of the custom rules
+pub fn validate(raw_gtfs: &gtfs_structures::RawGtfs, custom_rules: &CustomRules) -> Vec<Issue> {
+    let (start, end) = match metadatas::validity_period(raw_gtfs) {
+        Some(period) => period,
+        None => return vec![],
+    };
+    let today = custom_rules.reference_date();
+    let warning_days = custom_rules
+        .expiration_warning_days
+        .unwrap_or(EXPIRATION_WARNING_DAYS);
+    let max_days_before_start = custom_rules
+        .max_days_before_start
+        .unwrap_or(MAX_DAYS_BEFORE_START);
+
+    let mut issues = vec![];
+    let days_to_end = (end - today).num_days();
+    if days_to_end < 0 {
+        issues.push(
+            Issue::new(
+                Severity::Warning,
+                IssueType::ExpiredFeed,
+                service_on(raw_gtfs, end),
+            )
+            .object_type(ObjectType::Calendar)
+            .details(&format!(
+                "The feed ended on {}, {} days before {}",
+                end, -days_to_end, today
+            )),
+        );
+    } else if days_to_end < i64::from(warning_days) {
+        issues.push(
+            Issue::new(
+                Severity::Information,
+                IssueType::FeedExpiringSoon,
+                service_on(raw_gtfs, end),
+            )
+            .object_type(ObjectType::Calendar)
+            .details(&format!(
+                "The feed ends on {}, {} days after {}",
+                end, days_to_end, today
+            )),
+        );
+    }
+    let days_to_start = (start - today).num_days();
+    if days_to_start > i64::from(max_days_before_start) {
+        issues.push(
+            Issue::new(
+                Severity::Warning,
+                IssueType::FeedStartsInFuture,
+                service_on(raw_gtfs, start),
+            )
+            .object_type(ObjectType::Calendar)
+            .details(&format!(
+                "The feed starts on {}, {} days after {}",
+                start, days_to_start, today
+            )),
+        );
+    }
+    issues
+}
+
+#[cfg(test)]
+fn validate_on(date: &str, custom_rules: CustomRules) -> Vec<Issue> {
+    // the calendar of the feed runs from 2007-01-01, and a date is added on 2010-12-31
+    let raw_gtfs = gtfs_structures::RawGtfs::new("test_data/feed_validity").unwrap();
+    validate(
+        &raw_gtfs,
+        &CustomRules {
+            reference_date: Some(date.parse().unwrap()),
+            ..custom_rules
+        },
+    )
+}
+
+#[test]
+fn test_expired() {
+    let issues = validate_on("2011-01-10", CustomRules::default());
+
+    assert_eq!(1, issues.len());
+    assert_eq!(IssueType::ExpiredFeed, issues[0].issue_type);
+    // the service running on the last date of the feed
+    assert_eq!("EXTRA", issues[0].object_id);
+    assert_eq!(Some(ObjectType::Calendar), issues[0].object_type);
+    assert_eq!(
+        Some("The feed ended on 2010-12-31, 10 days before 2011-01-10".to_owned()),
+        issues[0].details
+    );
+    assert!(validate_on("2010-12-01", CustomRules::default()).is_empty());
+}
+
+#[test]
+fn test_expiring_soon() {
+    let issues = validate_on("2010-12-29", CustomRules::default());
+
+    assert_eq!(1, issues.len());
+    assert_eq!(IssueType::FeedExpiringSoon, issues[0].issue_type);
+    assert_eq!(Severity::Information, issues[0].severity);
+
+    let custom_rules = CustomRules {
+        expiration_warning_days: Some(60),
+        ..Default::default()
+    };
+    let issues = validate_on("2010-12-01", custom_rules);
+    assert_eq!(1, issues.len());
+    assert_eq!(
+        Some("The feed ends on 2010-12-31, 30 days after 2010-12-01".to_owned()),
+        issues[0].details
+    );
+}
+
+#[test]
+fn test_starts_in_future() {
+    let issues = validate_on("2006-11-01", CustomRules::default());
+
+    assert_eq!(1, issues.len());
+    assert_eq!(IssueType::FeedStartsInFuture, issues[0].issue_type);
+    assert_eq!("WEEK", issues[0].object_id);
+    assert_eq!(
+        Some("The feed starts on 2007-01-01, 61 days after 2006-11-01".to_owned()),
+        issues[0].details
+    );
+    assert!(validate_on("2006-12-15", CustomRules::default()).is_empty());
+}
@@ -16,7 +16,7 @@ const OPTIONAL_FILES: &[&str] = &[
     "timeframes.txt",
     "fare_media.txt",
     "fare_products.txt",
-    "tarif_leg_rules.txt",
+    "fare_leg_rules.txt",
     "fare_leg_join_rules.txt",
     "fare_transfer_rules.txt",
     "areas.txt",
@@ -0,0 +1,471 @@
+use super::utils::dedup_by_object;
+#[cfg(test)]
+use super::utils::issues_of_type;
+use crate::extra_files::{BookingRule, ExtraFiles, FlexStopTime};
+use crate::issues::{Issue, IssueType, Severity};
+use geo::Validation;
+use geojson::{Feature, GeometryValue, PolygonType, feature::Id};
+use gtfs_structures::ObjectType;
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashSet};
+
+/// The kinds of objects referenced by the GTFS-Flex files.
+#[derive(Clone, Copy)]
+enum Kind {
+    Stop,
+    Location,
+    LocationGroup,
+    BookingRule,
+    Service,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Stop => "stop",
+            Kind::Location => "location",
+            Kind::LocationGroup => "location group",
+            Kind::BookingRule => "booking rule",
+            Kind::Service => "service",
+        }
+    }
+}
+
+/// The ids of the objects that can be referenced, none if their file could not be read.
+struct Ids<'a> {
+    stops: Option<HashSet<&'a str>>,
+    locations: Option<HashSet<String>>,
+    location_groups: Option<HashSet<&'a str>>,
+    booking_rules: Option<HashSet<&'a str>>,
+    services: Option<HashSet<&'a str>>,
+}
+
+// An absent file has no ids
+fn ids<'a, T>(
+    file: &'a Option<anyhow::Result<Vec<T>>>,
+    id: impl Fn(&'a T) -> &'a str,
+) -> Option<HashSet<&'a str>> {
+    match file {
+        Some(Ok(objects)) => Some(objects.iter().map(id).collect()),
+        Some(Err(_)) => None,
+        None => Some(HashSet::new()),
+    }
+}
+
+impl<'a> Ids<'a> {
+    fn new(raw_gtfs: &'a gtfs_structures::RawGtfs, extra_files: &'a ExtraFiles) -> Self {
+        let calendar = match &raw_gtfs.calendar {
+            Some(Ok(calendar)) => Some(calendar.iter().map(|c| c.id.as_str()).collect_vec()),
+            Some(Err(_)) => None,
+            None => Some(vec![]),
+        };
+        let calendar_dates = match &raw_gtfs.calendar_dates {
+            Some(Ok(dates)) => Some(dates.iter().map(|d| d.service_id.as_str()).collect_vec()),
+            Some(Err(_)) => None,
+            None => Some(vec![]),
+        };
+        Ids {
+            stops: raw_gtfs
+                .stops
+                .as_ref()
+                .ok()
+                .map(|stops| stops.iter().map(|s| s.id.as_str()).collect()),
+            locations: match &extra_files.locations {
+                Some(Ok(locations)) => {
+                    Some(locations.features.iter().filter_map(feature_id).collect())
+                }
+                Some(Err(_)) => None,
+                None => Some(HashSet::new()),
+            },
+            location_groups: ids(&extra_files.location_groups, |g| &g.location_group_id),
+            booking_rules: ids(&extra_files.booking_rules, |r| &r.booking_rule_id),
+            services: calendar
+                .zip(calendar_dates)
+                .map(|(calendar, dates)| calendar.into_iter().chain(dates).collect()),
+        }
+    }
+
+    fn check_ref(&self, kind: Kind, id: &Option<String>, referenced_by: &str) -> Option<Issue> {
+        let id = id.as_deref()?;
+        let known = match kind {
+            Kind::Stop => self.stops.as_ref()?.contains(id),
+            Kind::Location => self.locations.as_ref()?.contains(id),
+            Kind::LocationGroup => self.location_groups.as_ref()?.contains(id),
+            Kind::BookingRule => self.booking_rules.as_ref()?.contains(id),
+            Kind::Service => self.services.as_ref()?.contains(id),
+        };
+        if known {
+            return None;
+        }
+        let issue = Issue::new(Severity::Fatal, IssueType::InvalidReference, id).details(&format!(
+            "The {} is referenced by {} but does not exist",
+            kind.name(),
+            referenced_by
+        ));
+        Some(match kind {
+            Kind::Stop => issue.object_type(ObjectType::Stop),
+            Kind::Service => issue.object_type(ObjectType::Calendar),
+            Kind::Location | Kind::LocationGroup | Kind::BookingRule => issue,
+        })
+    }
+}
+
+fn feature_id(feature: &Feature) -> Option<String> {
+    match feature.id.as_ref()? {
+        Id::String(id) => Some(id.clone()),
+        Id::Number(id) => Some(id.to_string()),
+    }
+}
+
+fn check_references(extra_files: &ExtraFiles, ids: &Ids) -> Vec<Issue> {
+    let mut issues = vec![];
+    if let Some(Ok(stop_times)) = &extra_files.flex_stop_times {
+        for st in stop_times {
+            let by = "a stop time";
+            issues.push(ids.check_ref(Kind::Location, &st.location_id, by));
+            issues.push(ids.check_ref(Kind::LocationGroup, &st.location_group_id, by));
+            issues.push(ids.check_ref(Kind::BookingRule, &st.pickup_booking_rule_id, by));
+            issues.push(ids.check_ref(Kind::BookingRule, &st.drop_off_booking_rule_id, by));
+        }
+    }
+    if let Some(Ok(group_stops)) = &extra_files.location_group_stops {
+        for gs in group_stops {
+            let by = "a location group stop";
+            issues.push(ids.check_ref(
+                Kind::LocationGroup,
+                &Some(gs.location_group_id.clone()),
+                by,
+            ));
+            issues.push(ids.check_ref(Kind::Stop, &Some(gs.stop_id.clone()), by));
+        }
+    }
+    if let Some(Ok(rules)) = &extra_files.booking_rules {
+        for r in rules {
+            issues.push(ids.check_ref(Kind::Service, &r.prior_notice_service_id, "a booking rule"));
+        }
+    }
+    dedup_by_object(issues.into_iter().flatten())
+}
+
+// The rings of the polygons must be closed, with valid coordinates
+fn check_rings(polygons: &[&PolygonType]) -> Option<&'static str> {
+    let rings = || polygons.iter().flat_map(|p| p.iter());
+    if rings().any(|ring| ring.len() < 4) {
+        Some("a ring of the location has less than 4 positions")
+    } else if rings()
+        .any(|ring| ring.first().map(|p| p.as_slice()) != ring.last().map(|p| p.as_slice()))
+    {
+        Some("a ring of the location is not closed")
+    } else if rings()
+        .flatten()
+        .any(|p| p.len() < 2 || !(-180. ..=180.).contains(&p[0]) || !(-90. ..=90.).contains(&p[1]))
+    {
+        Some("the location has invalid coordinates")
+    } else {
+        None
+    }
+}
+
+fn check_geometry(feature: &Feature) -> Option<String> {
+    let value = match &feature.geometry {
+        Some(geometry) => &geometry.value,
+        None => return Some("the location has no geometry".to_owned()),
+    };
+    let polygons = match value {
+        GeometryValue::Polygon { coordinates } => vec![coordinates],
+        GeometryValue::MultiPolygon { coordinates } => coordinates.iter().collect(),
+        other => {
+            return Some(format!(
+                "the location is a {}, but only Polygon and MultiPolygon are allowed",
+                other.type_name()
+            ));
+        }
+    };
+    if let Some(reason) = check_rings(&polygons) {
+        return Some(reason.to_owned());
+    }
+    let geometry = geo::Geometry::<f64>::try_from(value).ok()?;
+    geometry
+        .check_validation()
+        .err()
+        .map(|e| format!("the geometry of the location is not valid: {}", e))
+}
+
+fn check_locations(extra_files: &ExtraFiles) -> Vec<Issue> {
+    let locations = match &extra_files.locations {
+        Some(Ok(locations)) => locations,
+        _ => return vec![],
+    };
+    locations
+        .features
+        .iter()
+        .filter_map(|feature| {
+            let id = feature_id(feature);
+            let reason = match &id {
+                None => Some("the location has no id".to_owned()),
+                Some(_) => check_geometry(feature),
+            }?;
+            Some(
+                Issue::new(
+                    Severity::Error,
+                    IssueType::InvalidLocation,
+                    id.as_deref().unwrap_or_default(),
+                )
+                .details(&reason),
+            )
+        })
+        .collect()
+}
+
+// The stops, the locations and the location groups share the same ids
+fn check_duplicate_ids(ids: &Ids) -> Vec<Issue> {
+    let stops = ids.stops.iter().flatten().copied();
+    let locations = ids.locations.iter().flatten().map(|id| id.as_str());
+    let groups = ids.location_groups.iter().flatten().copied();
+    // sorted by id, for the issues to always be in the same order
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for id in stops.chain(locations).chain(groups) {
+        *counts.entry(id).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(id, _)| {
+            Issue::new(Severity::Error, IssueType::DuplicateObjectId, id)
+                .details("The id is shared by stops, locations or location groups")
+        })
+        .collect()
+}
+
+fn check_stop_time(st: &FlexStopTime) -> Option<&'static str> {
+    let window = (
+        st.start_pickup_drop_off_window,
+        st.end_pickup_drop_off_window,
+    );
+    let places = [&st.stop_id, &st.location_group_id, &st.location_id];
+    if places.iter().filter(|p| p.is_some()).count() != 1 {
+        Some("exactly one of stop_id, location_group_id and location_id must be given")
+    } else if window.0.is_some() != window.1.is_some() {
+        Some("start_pickup_drop_off_window and end_pickup_drop_off_window must be both given")
+    } else if st.is_flex() && window.0.is_none() {
+        Some("a stop time at a location or a location group requires a pickup/drop-off window")
+    } else if window.0.is_some() && (st.arrival_time.is_some() || st.departure_time.is_some()) {
+        Some("arrival_time and departure_time are forbidden with a pickup/drop-off window")
+    } else if let (Some(start), Some(end)) = window
+        && end <= start
+    {
+        Some("the pickup/drop-off window must end after its start")
+    } else if window.0.is_some() && matches!(st.pickup_type, Some(0) | Some(3)) {
+        Some("pickup_type 0 and 3 are forbidden with a pickup/drop-off window")
+    } else if window.0.is_some() && st.drop_off_type == Some(0) {
+        Some("drop_off_type 0 is forbidden with a pickup/drop-off window")
+    } else {
+        None
+    }
+}
+
+fn check_stop_times(extra_files: &ExtraFiles) -> Vec<Issue> {
+    let stop_times = match &extra_files.flex_stop_times {
+        Some(Ok(stop_times)) => stop_times,
+        _ => return vec![],
+    };
+    stop_times
+        .iter()
+        .filter_map(|st| {
+            check_stop_time(st).map(|reason| {
+                Issue::new(Severity::Error, IssueType::InvalidFlexStopTime, &st.trip_id)
+                    .object_type(ObjectType::Trip)
+                    .details(&format!(
+                        "At stop_sequence {}, {}",
+                        st.stop_sequence, reason
+                    ))
+            })
+        })
+        .collect()
+}
+
+// The prior notice fields depend on the type of the booking
+fn check_booking_rule(r: &BookingRule) -> Option<&'static str> {
+    let (same_day, prior_days) = (r.booking_type == 1, r.booking_type == 2);
+    if r.booking_type > 2 {
+        Some("booking_type must be 0, 1 or 2")
+    } else if r.prior_notice_duration_min.is_some() != same_day {
+        Some("prior_notice_duration_min is required for booking_type 1, and forbidden otherwise")
+    } else if r.prior_notice_duration_max.is_some() && !same_day {
+        Some("prior_notice_duration_max is only allowed for booking_type 1")
+    } else if let (Some(min), Some(max)) =
+        (r.prior_notice_duration_min, r.prior_notice_duration_max)
+        && max < min
+    {
+        Some("prior_notice_duration_max must not be less than prior_notice_duration_min")
+    } else if r.prior_notice_last_day.is_some() != prior_days {
+        Some("prior_notice_last_day is required for booking_type 2, and forbidden otherwise")
+    } else if r.prior_notice_last_time.is_some() != r.prior_notice_last_day.is_some() {
+        Some("prior_notice_last_time must be given if and only if prior_notice_last_day is")
+    } else if r.prior_notice_start_day.is_some()
+        && (r.booking_type == 0 || r.prior_notice_duration_max.is_some())
+    {
+        Some(
+            "prior_notice_start_day is forbidden for booking_type 0, and with a prior_notice_duration_max",
+        )
+    } else if r.prior_notice_start_time.is_some() != r.prior_notice_start_day.is_some() {
+        Some("prior_notice_start_time must be given if and only if prior_notice_start_day is")
+    } else if r.prior_notice_service_id.is_some() && !prior_days {
+        Some("prior_notice_service_id is only allowed for booking_type 2")
+    } else {
+        None
+    }
+}
+
+fn check_booking_rules(extra_files: &ExtraFiles) -> Vec<Issue> {
+    let rules = match &extra_files.booking_rules {
+        Some(Ok(rules)) => rules,
+        _ => return vec![],
+    };
+    rules
+        .iter()
+        .filter_map(|r| {
+            check_booking_rule(r).map(|reason| {
+                Issue::new(
+                    Severity::Error,
+                    IssueType::InvalidBookingRule,
+                    &r.booking_rule_id,
+                )
+                .details(reason)
+            })
+        })
+        .collect()
+}
+
+/// Check the GTFS-Flex files: the locations, the location groups, the booking rules and the
+/// stop times using them
+pub fn validate(raw_gtfs: &gtfs_structures::RawGtfs, extra_files: &ExtraFiles) -> Vec<Issue> {
+    let ids = Ids::new(raw_gtfs, extra_files);
+    check_references(extra_files, &ids)
+        .into_iter()
+        .chain(check_locations(extra_files))
+        .chain(check_duplicate_ids(&ids))
+        .chain(check_stop_times(extra_files))
+        .chain(check_booking_rules(extra_files))
+        .collect()
+}
+
+// The issues of the given type found in the test data
+#[cfg(test)]
+fn issues(issue_type: IssueType) -> Vec<Issue> {
+    let raw_gtfs = gtfs_structures::RawGtfs::new("test_data/flex").unwrap();
+    let extra_files = ExtraFiles::from_path("test_data/flex");
+    issues_of_type(validate(&raw_gtfs, &extra_files), issue_type)
+}
+
+#[test]
+fn test_references() {
+    let issues = issues(IssueType::InvalidReference)
+        .into_iter()
+        .map(|i| (i.object_id, i.details.unwrap()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        vec![
+            (
+                "unknown_group".to_owned(),
+                "The location group is referenced by a stop time but does not exist".to_owned()
+            ),
+            (
+                "unknown_location".to_owned(),
+                "The location is referenced by a stop time but does not exist".to_owned()
+            ),
+            (
+                "unknown_rule".to_owned(),
+                "The booking rule is referenced by a stop time but does not exist".to_owned()
+            ),
+            (
+                "unknown_service".to_owned(),
+                "The service is referenced by a booking rule but does not exist".to_owned()
+            ),
+            (
+                "unknown_stop".to_owned(),
+                "The stop is referenced by a location group stop but does not exist".to_owned()
+            ),
+        ],
+        issues
+    );
+}
+
+#[test]
+fn test_locations() {
+    let invalid = issues(IssueType::InvalidLocation)
+        .into_iter()
+        .map(|i| (i.object_id, i.details.unwrap()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        vec![
+            (
+                "bowtie".to_owned(),
+                "the geometry of the location is not valid: exterior ring has a self-intersection"
+                    .to_owned()
+            ),
+            (
+                "open".to_owned(),
+                "a ring of the location is not closed".to_owned()
+            ),
+            (
+                "point".to_owned(),
+                "the location is a Point, but only Polygon and MultiPolygon are allowed".to_owned()
+            ),
+        ],
+        invalid
+    );
+
+    let duplicates = issues(IssueType::DuplicateObjectId);
+    assert_eq!(1, duplicates.len());
+    assert_eq!("A", duplicates[0].object_id);
+}
+
+#[test]
+fn test_stop_times() {
+    let issues = issues(IssueType::InvalidFlexStopTime)
+        .into_iter()
+        .map(|i| i.details.unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        vec![
+            "At stop_sequence 1, exactly one of stop_id, location_group_id and location_id must be given",
+            "At stop_sequence 2, a stop time at a location or a location group requires a pickup/drop-off window",
+            "At stop_sequence 3, the pickup/drop-off window must end after its start",
+            "At stop_sequence 4, pickup_type 0 and 3 are forbidden with a pickup/drop-off window",
+        ],
+        issues
+    );
+}
+
+#[test]
+fn test_booking_rules() {
+    let issues = issues(IssueType::InvalidBookingRule)
+        .into_iter()
+        .map(|i| (i.object_id, i.details.unwrap()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        vec![
+            (
+                "no_last_time".to_owned(),
+                "prior_notice_last_time must be given if and only if prior_notice_last_day is"
+                    .to_owned()
+            ),
+            (
+                "real_time_notice".to_owned(),
+                "prior_notice_duration_min is required for booking_type 1, and forbidden otherwise"
+                    .to_owned()
+            ),
+            (
+                "same_day_max".to_owned(),
+                "prior_notice_duration_max must not be less than prior_notice_duration_min"
+                    .to_owned()
+            ),
+        ],
+        issues
+    );
+}
@@ -0,0 +1,237 @@
+use super::utils::format_time;
+#[cfg(test)]
+use super::utils::issues_of_type;
+use crate::issues::{Issue, IssueType, Severity};
+use gtfs_structures::{Frequency, Trip};
+use itertools::Itertools;
+use std::collections::HashMap;
+
+/// Below one minute, a headway is most likely expressed in the wrong unit
+const MIN_HEADWAY: u32 = 60;
+/// Above six hours, a headway is most likely expressed in the wrong unit
+const MAX_HEADWAY: u32 = 6 * 3600;
+/// Number of scheduled trips, departing during its frequency windows, from which a
+/// frequency-based trip is considered to describe the same service a second time
+const MIN_SCHEDULED_TRIPS: usize = 2;
+/// To limit the size of the issue, we limit the number of related scheduled trips
+const MAX_TRIPS: usize = 20;
+
+pub fn validate(gtfs: &gtfs_structures::Gtfs) -> Vec<Issue> {
+    gtfs.trips
+        .values()
+        .filter(|trip| !trip.frequencies.is_empty())
+        .flat_map(|trip| {
+            check_windows(trip)
+                .into_iter()
+                .chain(check_overlaps(trip))
+                .chain(check_exact_times(trip))
+        })
+        .chain(check_scheduled_trips(gtfs))
+        .collect()
+}
+
+fn window(frequency: &Frequency) -> String {
+    format!(
+        "{}-{}",
+        format_time(frequency.start_time),
+        format_time(frequency.end_time)
+    )
+}
+
+fn check_windows(trip: &Trip) -> Vec<Issue> {
+    trip.frequencies
+        .iter()
+        .filter_map(|f| {
+            if f.start_time >= f.end_time {
+                Some(
+                    Issue::new_with_obj(Severity::Error, IssueType::InvalidFrequency, trip)
+                        .details(&format!(
+                            "The frequency window {} does not end after its start",
+                            window(f)
+                        )),
+                )
+            } else if f.headway_secs == 0 {
+                Some(
+                    Issue::new_with_obj(Severity::Error, IssueType::InvalidFrequency, trip)
+                        .details(&format!(
+                            "The headway of the frequency window {} is not positive",
+                            window(f)
+                        )),
+                )
+            } else if !(MIN_HEADWAY..=MAX_HEADWAY).contains(&f.headway_secs) {
+                Some(
+                    Issue::new_with_obj(Severity::Warning, IssueType::ImplausibleHeadway, trip)
+                        .details(&format!(
+                            "The headway of the frequency window {} is {} seconds",
+                            window(f),
+                            f.headway_secs
+                        )),
+                )
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// The frequency windows of a trip must not overlap, `end_time` being excluded from a window
+fn check_overlaps(trip: &Trip) -> Option<Issue> {
+    let overlaps = trip
+        .frequencies
+        .iter()
+        .filter(|f| f.start_time < f.end_time)
+        .sorted_by_key(|f| f.start_time)
+        .tuple_windows()
+        .filter(|(f1, f2)| f2.start_time < f1.end_time)
+        .map(|(f1, f2)| format!("{} and {}", window(f1), window(f2)))
+        .collect::<Vec<_>>();
+    if overlaps.is_empty() {
+        None
+    } else {
+        Some(
+            Issue::new_with_obj(Severity::Error, IssueType::OverlappingFrequencies, trip).details(
+                &format!("The frequency windows {} overlap", overlaps.join(", ")),
+            ),
+        )
+    }
+}
+
+// A trip is either run at exact times or not, the frequency windows should agree
+fn check_exact_times(trip: &Trip) -> Option<Issue> {
+    let exact_times = trip
+        .frequencies
+        .iter()
+        .map(|f| {
+            f.exact_times
+                .unwrap_or(gtfs_structures::ExactTimes::FrequencyBased)
+        })
+        .unique()
+        .count();
+    if exact_times > 1 {
+        Some(
+            Issue::new_with_obj(Severity::Warning, IssueType::InconsistentExactTimes, trip)
+                .details(
+                    "Some frequency windows of the trip are schedule-based and others are not",
+                ),
+        )
+    } else {
+        None
+    }
+}
+
+// The same stops served by the same route on the same days
+fn pattern(trip: &Trip) -> (&str, &str, Vec<&str>) {
+    (
+        &trip.route_id,
+        &trip.service_id,
+        trip.stop_times
+            .iter()
+            .map(|st| st.stop.id.as_str())
+            .collect(),
+    )
+}
+
+// A frequency-based trip whose service is also described by scheduled trips is counted twice
+fn check_scheduled_trips(gtfs: &gtfs_structures::Gtfs) -> impl Iterator<Item = Issue> + '_ {
+    let mut scheduled: HashMap<_, Vec<&Trip>> = HashMap::new();
+    for trip in gtfs.trips.values() {
+        if trip.frequencies.is_empty() && !trip.stop_times.is_empty() {
+            scheduled.entry(pattern(trip)).or_default().push(trip);
+        }
+    }
+
+    gtfs.trips
+        .values()
+        .filter(|trip| !trip.frequencies.is_empty() && !trip.stop_times.is_empty())
+        .filter_map(move |trip| {
+            let duplicates = scheduled
+                .get(&pattern(trip))?
+                .iter()
+                .filter(|t| {
+                    t.stop_times[0].departure_time.is_some_and(|departure| {
+                        trip.frequencies
+                            .iter()
+                            .any(|f| (f.start_time..f.end_time).contains(&departure))
+                    })
+                })
+                .sorted_by_key(|t| &t.id)
+                .collect::<Vec<_>>();
+            if duplicates.len() < MIN_SCHEDULED_TRIPS {
+                return None;
+            }
+            let mut issue =
+                Issue::new_with_obj(Severity::Warning, IssueType::FrequencyAlsoScheduled, trip)
+                    .details(&format!(
+                        "{} trips with the same route, service and stops depart during the frequency windows of the trip",
+                        duplicates.len()
+                    ));
+            for t in duplicates.into_iter().take(MAX_TRIPS) {
+                issue.push_related_object(*t);
+            }
+            Some(issue)
+        })
+}
+
+#[test]
+fn test_invalid_frequencies() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/frequencies").unwrap();
+    let invalid = issues_of_type(validate(&gtfs), IssueType::InvalidFrequency);
+
+    assert_eq!(2, invalid.len());
+    assert_eq!("null_headway", invalid[0].object_id);
+    assert_eq!(
+        Some("The headway of the frequency window 06:00:00-08:00:00 is not positive".to_owned()),
+        invalid[0].details
+    );
+    assert_eq!("reversed", invalid[1].object_id);
+    assert_eq!(
+        Some("The frequency window 10:00:00-08:00:00 does not end after its start".to_owned()),
+        invalid[1].details
+    );
+
+    let implausible = issues_of_type(validate(&gtfs), IssueType::ImplausibleHeadway);
+    assert_eq!(1, implausible.len());
+    assert_eq!("minutes_headway", implausible[0].object_id);
+    assert_eq!(Severity::Warning, implausible[0].severity);
+}
+
+#[test]
+fn test_overlapping_frequencies() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/frequencies").unwrap();
+    let overlapping = issues_of_type(validate(&gtfs), IssueType::OverlappingFrequencies);
+
+    // consecutive windows, sharing their bound, do not overlap
+    assert_eq!(1, overlapping.len());
+    assert_eq!("overlapping", overlapping[0].object_id);
+    assert_eq!(
+        Some("The frequency windows 06:00:00-09:00:00 and 08:00:00-10:00:00 overlap".to_owned()),
+        overlapping[0].details
+    );
+}
+
+#[test]
+fn test_inconsistent_exact_times() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/frequencies").unwrap();
+    let inconsistent = issues_of_type(validate(&gtfs), IssueType::InconsistentExactTimes);
+
+    assert_eq!(1, inconsistent.len());
+    assert_eq!("mixed_exact_times", inconsistent[0].object_id);
+}
+
+#[test]
+fn test_frequency_also_scheduled() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/frequencies").unwrap();
+    let scheduled = issues_of_type(validate(&gtfs), IssueType::FrequencyAlsoScheduled);
+
+    assert_eq!(1, scheduled.len());
+    assert_eq!("consecutive", scheduled[0].object_id);
+    // the scheduled trip departing after the frequency windows is not related
+    assert_eq!(
+        vec!["scheduled_1", "scheduled_2"],
+        scheduled[0]
+            .related_objects
+            .iter()
+            .map(|o| o.id.as_str())
+            .collect::<Vec<_>>()
+    );
+}
@@ -109,6 +109,27 @@ impl Ids {
             .collect()
     }
 
+    fn check_frequencies(
+        &self,
+        frequencies: &Option<Result<Vec<gtfs_structures::RawFrequency>, gtfs_structures::Error>>,
+    ) -> Vec<Issue> {
+        match frequencies {
+            Some(Ok(frequencies)) => frequencies
+                .iter()
+                .filter_map(|f| {
+                    self.check_ref(&f.trip_id, gtfs_structures::ObjectType::Trip)
+                        .map(|i| {
+                            i.details("The trip is referenced by a frequency but does not exist")
+                        })
+                })
+                .map(|i| (i.object_id.clone(), i))
+                .collect::<HashMap<_, _>>()
+                .into_values()
+                .collect(),
+            _ => vec![],
+        }
+    }
+
     fn check_routes(
         &self,
         routes: &Result<Vec<gtfs_structures::Route>, gtfs_structures::Error>,
@@ -161,6 +182,7 @@ impl Ids {
 /// There are not that many link in the gtfs, we check:
 /// * the stop times's stops and trips
 /// * the trips routes and calendar
+/// * the frequencies trips
 pub fn validate(raw_gtfs: &gtfs_structures::RawGtfs) -> Vec<Issue> {
     let id_container = Ids::new(raw_gtfs);
 
@@ -170,6 +192,7 @@ pub fn validate(raw_gtfs: &gtfs_structures::RawGtfs) -> Vec<Issue> {
         .chain(id_container.check_trips(&raw_gtfs.trips))
         .chain(id_container.check_routes(&raw_gtfs.routes))
         .chain(id_container.check_stops(&raw_gtfs.stops))
+        .chain(id_container.check_frequencies(&raw_gtfs.frequencies))
         .collect()
 }
 
@@ -179,7 +202,7 @@ fn test() {
     let gtfs = gtfs_structures::RawGtfs::new("test_data/invalid_references").unwrap();
     let issues = validate(&gtfs);
 
-    assert_eq!(issues.len(), 6);
+    assert_eq!(issues.len(), 7);
 
     let unknown_stop_issue = issues
         .iter()
@@ -280,4 +303,18 @@ fn test() {
         unknown_stop_parent_issue.details,
         Some("The stop is referenced as a stop's parent_station but does not exist".to_owned())
     );
+
+    let unknown_frequency_trip_issue = issues
+        .iter()
+        .find(|i| i.object_id == "unknown_frequency_trip")
+        .expect("impossible to find the issue");
+
+    assert_eq!(
+        unknown_frequency_trip_issue.object_type,
+        Some(ObjectType::Trip)
+    );
+    assert_eq!(
+        unknown_frequency_trip_issue.details,
+        Some("The trip is referenced by a frequency but does not exist".to_owned())
+    );
 }
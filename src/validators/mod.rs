@@ -1,19 +1,33 @@
 pub mod agency;
+pub mod blocks;
 pub mod calendar;
 pub mod check_id;
 pub mod check_name;
 pub mod duplicate_stops;
+pub mod duplicate_trips;
 pub mod duration_distance;
+pub mod extra_files;
 pub mod fare_attributes;
+pub mod fare_rules;
+pub mod fares_v2;
 pub mod feed_info;
+pub mod feed_validity;
 pub mod file_presence;
+pub mod flex;
+pub mod frequencies;
 pub mod interpolated_stoptimes;
 pub mod invalid_reference;
+pub mod pathways;
 pub mod raw_gtfs;
 pub mod routes;
+pub mod service_gaps;
+pub mod shape_dist_traveled;
+pub mod shape_stops;
 pub mod shapes;
 pub mod stop_times;
 pub mod stops;
 pub mod sub_folder;
+pub mod transfers;
 pub mod unusable_trip;
 pub mod unused_stop;
+mod utils;
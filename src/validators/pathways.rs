@@ -0,0 +1,329 @@
+use super::utils::dedup_by_object;
+#[cfg(test)]
+use super::utils::issues_of_type;
+use crate::extra_files::ExtraFiles;
+use crate::issues::{Issue, IssueType, Severity};
+use gtfs_structures::{
+    LocationType, ObjectType, PathwayDirectionType, PathwayMode, RawPathway, Stop,
+};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+// The stops by id, sorted for the issues to always be in the same order
+type Stops<'a> = BTreeMap<&'a str, &'a Stop>;
+
+fn pathway_issue(severity: Severity, pathway: &RawPathway, details: &str) -> Issue {
+    Issue::new(severity, IssueType::InvalidPathway, &pathway.id)
+        .object_type(ObjectType::Pathway)
+        .details(details)
+}
+
+fn check_references(pathways: &[RawPathway], stops: &Stops) -> Vec<Issue> {
+    dedup_by_object(
+        pathways
+            .iter()
+            .flat_map(|p| [&p.from_stop_id, &p.to_stop_id])
+            .filter(|id| !stops.contains_key(id.as_str()))
+            .map(|id| {
+                Issue::new(Severity::Fatal, IssueType::InvalidReference, id)
+                    .object_type(ObjectType::Stop)
+                    .details("The stop is referenced by a pathway but does not exist")
+            }),
+    )
+}
+
+// A pathway links entrances, generic nodes, boarding areas or platforms, never stations
+fn check_endpoints<'a>(
+    pathways: &'a [RawPathway],
+    stops: &'a Stops,
+) -> impl Iterator<Item = Issue> + 'a {
+    pathways.iter().flat_map(move |p| {
+        [&p.from_stop_id, &p.to_stop_id]
+            .into_iter()
+            .filter_map(|id| stops.get(id.as_str()))
+            .filter(|stop| {
+                !matches!(
+                    stop.location_type,
+                    LocationType::StopPoint
+                        | LocationType::StationEntrance
+                        | LocationType::GenericNode
+                        | LocationType::BoardingArea
+                )
+            })
+            .map(|stop| {
+                pathway_issue(
+                    Severity::Error,
+                    p,
+                    &format!(
+                        "The pathway links the stop {}, a {:?}, but only platforms, entrances, generic nodes and boarding areas can be linked",
+                        stop.id, stop.location_type
+                    ),
+                )
+                .add_related_object(*stop)
+            })
+            .collect::<Vec<_>>()
+    })
+}
+
+// Some fields only make sense for some modes, and must be positive
+fn check_mode_fields(pathway: &RawPathway) -> Vec<Issue> {
+    let mut issues = vec![];
+    if pathway.stair_count.is_some() && pathway.mode != PathwayMode::Stairs {
+        issues.push(pathway_issue(
+            Severity::Warning,
+            pathway,
+            &format!(
+                "stair_count is only for stairs, the pathway is a {:?}",
+                pathway.mode
+            ),
+        ));
+    }
+    if pathway.max_slope.is_some()
+        && !matches!(
+            pathway.mode,
+            PathwayMode::Walkway | PathwayMode::MovingSidewalk
+        )
+    {
+        issues.push(pathway_issue(
+            Severity::Warning,
+            pathway,
+            &format!(
+                "max_slope is only for walkways and moving sidewalks, the pathway is a {:?}",
+                pathway.mode
+            ),
+        ));
+    }
+    if pathway.length.is_some_and(|l| l < 0.) {
+        issues.push(pathway_issue(
+            Severity::Error,
+            pathway,
+            "length must not be negative",
+        ));
+    }
+    if pathway.min_width.is_some_and(|w| w <= 0.) {
+        issues.push(pathway_issue(
+            Severity::Error,
+            pathway,
+            "min_width must be positive",
+        ));
+    }
+    if pathway.traversal_time == Some(0) {
+        issues.push(pathway_issue(
+            Severity::Error,
+            pathway,
+            "traversal_time must be positive",
+        ));
+    }
+    issues
+}
+
+fn check_levels(
+    raw_gtfs: &gtfs_structures::RawGtfs,
+    extra_files: &ExtraFiles,
+    stops: &Stops,
+) -> Vec<Issue> {
+    let levels: HashSet<&str> = match &extra_files.levels {
+        Some(Ok(levels)) => levels.iter().map(|l| l.level_id.as_str()).collect(),
+        // without levels.txt, no level can be referenced
+        None if !raw_gtfs.files.iter().any(|f| f.ends_with("levels.txt")) => HashSet::new(),
+        _ => return vec![],
+    };
+    // sorted by level, for the issues to always be in the same order
+    let mut issues = BTreeMap::new();
+    for stop in stops.values() {
+        if let Some(level_id) = &stop.level_id
+            && !levels.contains(level_id.as_str())
+        {
+            issues
+                .entry(level_id)
+                .or_insert_with(|| {
+                    Issue::new(Severity::Fatal, IssueType::InvalidReference, level_id)
+                        .details("The level is referenced by a stop but does not exist")
+                })
+                .push_related_object(*stop);
+        }
+    }
+    issues.into_values().collect()
+}
+
+// The locations reachable from the given ones, following the pathways
+fn reachable<'a>(
+    from: impl Iterator<Item = &'a str>,
+    graph: &HashMap<&'a str, Vec<&'a str>>,
+) -> HashSet<&'a str> {
+    let mut reached: HashSet<&str> = from.collect();
+    let mut queue: VecDeque<&str> = reached.iter().copied().collect();
+    while let Some(location) = queue.pop_front() {
+        for next in graph.get(location).into_iter().flatten() {
+            if reached.insert(next) {
+                queue.push_back(next);
+            }
+        }
+    }
+    reached
+}
+
+// Within a station with pathways, each platform must be reachable from an entrance, and lead to one.
+// The generic nodes that can be reached but not left are dead ends.
+fn check_graph(pathways: &[RawPathway], stops: &Stops) -> Vec<Issue> {
+    let mut forward: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut backward: HashMap<&str, Vec<&str>> = HashMap::new();
+    for p in pathways {
+        let (from, to) = (p.from_stop_id.as_str(), p.to_stop_id.as_str());
+        forward.entry(from).or_default().push(to);
+        backward.entry(to).or_default().push(from);
+        if p.is_bidirectional == PathwayDirectionType::Bidirectional {
+            forward.entry(to).or_default().push(from);
+            backward.entry(from).or_default().push(to);
+        }
+    }
+
+    // the station of each location, the boarding areas belonging to a platform
+    let station = |stop: &Stop| -> Option<String> {
+        let parent = stops.get(stop.parent_station.as_deref()?)?;
+        if stop.location_type == LocationType::BoardingArea {
+            parent.parent_station.clone()
+        } else {
+            Some(parent.id.clone())
+        }
+    };
+    let with_pathways: HashSet<String> = forward
+        .keys()
+        .chain(backward.keys())
+        .filter_map(|id| stops.get(id))
+        .filter_map(|stop| station(stop))
+        .collect();
+    let platforms_with_boarding_areas: HashSet<&str> = stops
+        .values()
+        .filter(|s| s.location_type == LocationType::BoardingArea)
+        .filter_map(|s| s.parent_station.as_deref())
+        .collect();
+    let located = |location_type: LocationType| {
+        stops
+            .values()
+            .filter(move |s| s.location_type == location_type)
+            .filter(|s| station(s).is_some_and(|st| with_pathways.contains(&st)))
+    };
+
+    let entrances = || located(LocationType::StationEntrance).map(|s| s.id.as_str());
+    let from_entrances = reachable(entrances(), &forward);
+    let to_entrances = reachable(entrances(), &backward);
+
+    let mut issues = vec![];
+    let platforms = located(LocationType::StopPoint)
+        .filter(|s| !platforms_with_boarding_areas.contains(s.id.as_str()))
+        .chain(located(LocationType::BoardingArea));
+    for platform in platforms {
+        let reasons = [
+            (!from_entrances.contains(platform.id.as_str()))
+                .then_some("it cannot be reached from an entrance"),
+            (!to_entrances.contains(platform.id.as_str()))
+                .then_some("no entrance can be reached from it"),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        if !reasons.is_empty() {
+            issues.push(
+                Issue::new_with_obj(Severity::Warning, IssueType::UnreachablePlatform, *platform)
+                    .details(&format!(
+                        "Following the pathways, {}",
+                        reasons.join(" and ")
+                    )),
+            );
+        }
+    }
+    for node in located(LocationType::GenericNode) {
+        if backward.contains_key(node.id.as_str()) && !forward.contains_key(node.id.as_str()) {
+            issues.push(
+                Issue::new_with_obj(Severity::Warning, IssueType::PathwayDeadEnd, *node)
+                    .details("The pathways lead to this node, but none leave it"),
+            );
+        }
+    }
+    issues
+}
+
+/// Check the pathways of the stations, and the levels of the stops
+pub fn validate(raw_gtfs: &gtfs_structures::RawGtfs, extra_files: &ExtraFiles) -> Vec<Issue> {
+    let stops: Stops = match &raw_gtfs.stops {
+        Ok(stops) => stops.iter().map(|s| (s.id.as_str(), s)).collect(),
+        Err(_) => return vec![],
+    };
+    let levels = check_levels(raw_gtfs, extra_files, &stops);
+    let pathways = match &raw_gtfs.pathways {
+        Some(Ok(pathways)) => pathways,
+        _ => return levels,
+    };
+
+    check_references(pathways, &stops)
+        .into_iter()
+        .chain(check_endpoints(pathways, &stops))
+        .chain(pathways.iter().flat_map(check_mode_fields))
+        .chain(check_graph(pathways, &stops))
+        .chain(levels)
+        .collect()
+}
+
+// The issues of the given type found in the test data
+#[cfg(test)]
+fn issues(issue_type: IssueType) -> Vec<Issue> {
+    let raw_gtfs = gtfs_structures::RawGtfs::new("test_data/pathways").unwrap();
+    let extra_files = ExtraFiles::from_path("test_data/pathways");
+    issues_of_type(validate(&raw_gtfs, &extra_files), issue_type)
+}
+
+#[test]
+fn test_references() {
+    let issues = issues(IssueType::InvalidReference);
+
+    assert_eq!(2, issues.len());
+    assert_eq!("unknown_level", issues[0].object_id);
+    assert_eq!("P2", issues[0].related_objects[0].id);
+    assert_eq!("unknown_stop", issues[1].object_id);
+    assert_eq!(Some(ObjectType::Stop), issues[1].object_type);
+}
+
+#[test]
+fn test_invalid_pathways() {
+    let issues = issues(IssueType::InvalidPathway)
+        .into_iter()
+        .map(|i| (i.object_id, i.severity, i.details.unwrap()))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        vec![
+            ("pw4".to_owned(), Severity::Warning, "max_slope is only for walkways and moving sidewalks, the pathway is a Escalator".to_owned()),
+            ("pw5".to_owned(), Severity::Error, "The pathway links the stop S, a StopArea, but only platforms, entrances, generic nodes and boarding areas can be linked".to_owned()),
+            ("pw6".to_owned(), Severity::Error, "min_width must be positive".to_owned()),
+            ("pw6".to_owned(), Severity::Warning, "stair_count is only for stairs, the pathway is a Walkway".to_owned()),
+            ("pw7".to_owned(), Severity::Error, "traversal_time must be positive".to_owned()),
+        ],
+        issues
+    );
+}
+
+#[test]
+fn test_unreachable_platforms() {
+    let issues = issues(IssueType::UnreachablePlatform);
+
+    // P1 is reachable, Q belongs to a station without pathways, P2 has a boarding area
+    assert_eq!(2, issues.len());
+    assert_eq!("B2", issues[0].object_id);
+    assert_eq!(
+        Some("Following the pathways, no entrance can be reached from it".to_owned()),
+        issues[0].details
+    );
+    assert_eq!("P3", issues[1].object_id);
+    assert_eq!(
+        Some("Following the pathways, it cannot be reached from an entrance and no entrance can be reached from it".to_owned()),
+        issues[1].details
+    );
+}
+
+#[test]
+fn test_dead_ends() {
+    let issues = issues(IssueType::PathwayDeadEnd);
+
+    assert_eq!(1, issues.len());
+    assert_eq!("N2", issues[0].object_id);
+}
@@ -0,0 +1,271 @@
+use super::utils::feed_start;
+#[cfg(test)]
+use super::utils::issues_of_type;
+use crate::custom_rules::CustomRules;
+use crate::issues::{Issue, IssueType, Severity};
+use chrono::{Datelike, NaiveDate, TimeDelta};
+use gtfs_structures::{Agency, ObjectType, Route};
+use std::collections::{HashMap, HashSet};
+
+/// Under this fraction of its usual level for the same day of the week, the service is reported as dropping
+const SERVICE_DROP_RATIO: f64 = 0.2;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Kind {
+    Gap,
+    Drop,
+}
+
+/// Consecutive days, from the first to the last, without service or with a service drop.
+#[derive(Debug)]
+struct Run {
+    kind: Kind,
+    first: usize,
+    last: usize,
+}
+
+// The number of trips running each day of the validity period, by route
+fn trips_by_day(gtfs: &gtfs_structures::Gtfs) -> Option<(NaiveDate, HashMap<&str, Vec<usize>>)> {
+    let start = feed_start(gtfs)?;
+    let end = gtfs
+        .calendar
+        .values()
+        .map(|c| c.end_date)
+        .chain(gtfs.calendar_dates.values().flatten().map(|d| d.date))
+        .max()?;
+    let days = usize::try_from((end - start).num_days() + 1).ok()?;
+
+    let mut service_days: HashMap<&str, HashSet<usize>> = HashMap::new();
+    let mut trips: HashMap<&str, Vec<usize>> = HashMap::new();
+    for trip in gtfs.trips.values() {
+        let service_days = service_days
+            .entry(trip.service_id.as_str())
+            .or_insert_with(|| {
+                gtfs.trip_days(&trip.service_id, start)
+                    .into_iter()
+                    .map(usize::from)
+                    .filter(|day| *day < days)
+                    .collect()
+            });
+        let counts = trips
+            .entry(trip.route_id.as_str())
+            .or_insert_with(|| vec![0; days]);
+        for day in service_days.iter() {
+            counts[*day] += 1;
+        }
+    }
+    Some((start, trips))
+}
+
+// The median number of trips for each day of the week
+fn usual_levels(start: NaiveDate, counts: &[usize]) -> [usize; 7] {
+    let mut by_weekday: [Vec<usize>; 7] = Default::default();
+    for (day, count) in counts.iter().enumerate() {
+        by_weekday[weekday(start, day)].push(*count);
+    }
+    by_weekday.map(|mut counts| {
+        counts.sort_unstable();
+        counts.get(counts.len() / 2).copied().unwrap_or(0)
+    })
+}
+
+fn weekday(start: NaiveDate, day: usize) -> usize {
+    (start.weekday().num_days_from_monday() as usize + day) % 7
+}
+
+// The days usually without service don't interrupt a run, neither do the ignored ones
+fn find_runs(start: NaiveDate, counts: &[usize], ratio: f64, ignored: &HashSet<usize>) -> Vec<Run> {
+    // the whole validity period of the feed is checked, a route stopping early has a gap
+    let usual = usual_levels(start, counts);
+
+    let mut runs: Vec<Run> = vec![];
+    let mut current: Option<Run> = None;
+    for (day, count) in counts.iter().enumerate() {
+        let usual = usual[weekday(start, day)];
+        if usual == 0 || ignored.contains(&day) {
+            continue;
+        }
+        let kind = if *count == 0 {
+            Some(Kind::Gap)
+        } else if (*count as f64) < ratio * usual as f64 {
+            Some(Kind::Drop)
+        } else {
+            None
+        };
+        match (&mut current, kind) {
+            (Some(run), Some(kind)) if run.kind == kind => run.last = day,
+            (_, kind) => {
+                runs.extend(current.take());
+                current = kind.map(|kind| Run {
+                    kind,
+                    first: day,
+                    last: day,
+                });
+            }
+        }
+    }
+    runs.extend(current);
+    runs
+}
+
+fn describe(start: NaiveDate, run: &Run, ratio: f64) -> String {
+    let day = |d: usize| start + TimeDelta::days(d as i64);
+    let period = if run.first == run.last {
+        format!("On {}", day(run.first))
+    } else {
+        format!("From {} to {}", day(run.first), day(run.last))
+    };
+    match run.kind {
+        Kind::Gap => format!("{}, no trip runs", period),
+        Kind::Drop => format!(
+            "{}, the number of trips falls under {:.0}% of its usual level for the same day of the week",
+            period,
+            ratio * 100.
+        ),
+    }
+}
+
+fn severity_and_type(run: &Run) -> (Severity, IssueType) {
+    match run.kind {
+        Kind::Gap => (Severity::Warning, IssueType::ServiceGap),
+        Kind::Drop => (Severity::Information, IssueType::ServiceDrop),
+    }
+}
+
+fn agency<'a>(gtfs: &'a gtfs_structures::Gtfs, route: &Route) -> Option<&'a Agency> {
+    match &route.agency_id {
+        Some(id) => gtfs.agencies.iter().find(|a| a.id.as_ref() == Some(id)),
+        None if gtfs.agencies.len() == 1 => gtfs.agencies.first(),
+        None => None,
+    }
+}
+
+// The network of a route is its agency, even unknown
+fn network_id<'a>(gtfs: &'a gtfs_structures::Gtfs, route: &'a Route) -> Option<&'a str> {
+    agency(gtfs, route)
+        .and_then(|a| a.id.as_deref())
+        .or(route.agency_id.as_deref())
+}
+
+/// Check the number of trips of each day, for each network and for each route, to find the
+/// days without service or with much less service than usual
+pub fn validate(gtfs: &gtfs_structures::Gtfs, custom_rules: &CustomRules) -> Vec<Issue> {
+    let ratio = custom_rules
+        .service_drop_ratio
+        .unwrap_or(SERVICE_DROP_RATIO);
+    let Some((start, trips)) = trips_by_day(gtfs) else {
+        return vec![];
+    };
+
+    let mut networks: HashMap<Option<&str>, (Option<&Agency>, Vec<usize>)> = HashMap::new();
+    for (route_id, counts) in &trips {
+        let Some(route) = gtfs.routes.get(*route_id) else {
+            continue;
+        };
+        let (_, network_counts) = networks
+            .entry(network_id(gtfs, route))
+            .or_insert_with(|| (agency(gtfs, route), vec![0; counts.len()]));
+        for (total, count) in network_counts.iter_mut().zip(counts) {
+            *total += count;
+        }
+    }
+
+    let mut issues = vec![];
+    // the days without service for the whole network are not reported again for its routes
+    let mut network_gaps: HashMap<Option<&str>, HashSet<usize>> = HashMap::new();
+    for (agency_id, (agency, counts)) in &networks {
+        for run in find_runs(start, counts, ratio, &HashSet::new()) {
+            if run.kind == Kind::Gap {
+                network_gaps
+                    .entry(*agency_id)
+                    .or_default()
+                    .extend(run.first..=run.last);
+            }
+            let (severity, issue_type) = severity_and_type(&run);
+            let issue = match agency {
+                Some(agency) => Issue::new_with_obj(severity, issue_type, *agency),
+                None => Issue::new(severity, issue_type, agency_id.unwrap_or_default())
+                    .object_type(ObjectType::Agency)
+                    .name("Routes without a known agency"),
+            };
+            issues.push(issue.details(&describe(start, &run, ratio)));
+        }
+    }
+    for (route_id, counts) in &trips {
+        let Some(route) = gtfs.routes.get(*route_id) else {
+            continue;
+        };
+        let ignored = network_gaps
+            .get(&network_id(gtfs, route))
+            .cloned()
+            .unwrap_or_default();
+        for run in find_runs(start, counts, ratio, &ignored) {
+            let (severity, issue_type) = severity_and_type(&run);
+            issues.push(
+                Issue::new_with_obj(severity, issue_type, route)
+                    .details(&describe(start, &run, ratio)),
+            );
+        }
+    }
+    issues
+}
+
+#[test]
+fn test_gaps() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/service_gaps").unwrap();
+    let issues = issues_of_type(
+        validate(&gtfs, &CustomRules::default()),
+        IssueType::ServiceGap,
+    );
+
+    // the network has no service on 2024-03-04, that is not reported again for R1
+    assert_eq!(3, issues.len());
+    assert_eq!("DTA", issues[1].object_id);
+    assert_eq!(
+        Some("On 2024-03-04, no trip runs".to_owned()),
+        issues[1].details
+    );
+    assert_eq!("R1", issues[2].object_id);
+    assert_eq!(
+        Some("From 2024-02-12 to 2024-02-16, no trip runs".to_owned()),
+        issues[2].details
+    );
+    // R3, without agency, stops two weeks before the end of the feed
+    assert_eq!("", issues[0].object_id);
+    assert_eq!(
+        Some("Routes without a known agency".to_owned()),
+        issues[0].object_name
+    );
+    assert_eq!(
+        Some("From 2024-03-18 to 2024-03-29, no trip runs".to_owned()),
+        issues[0].details
+    );
+}
+
+#[test]
+fn test_drops() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/service_gaps").unwrap();
+    let issues = issues_of_type(
+        validate(&gtfs, &CustomRules::default()),
+        IssueType::ServiceDrop,
+    );
+
+    assert_eq!(1, issues.len());
+    assert_eq!("DTA", issues[0].object_id);
+    assert_eq!(
+        Some("From 2024-02-12 to 2024-02-16, the number of trips falls under 20% of its usual level for the same day of the week".to_owned()),
+        issues[0].details
+    );
+
+    // with a lower ratio, the drop is not reported
+    let gtfs = gtfs_structures::Gtfs::new("test_data/service_gaps").unwrap();
+    let custom_rules = CustomRules {
+        service_drop_ratio: Some(0.1),
+        ..Default::default()
+    };
+    assert!(
+        !validate(&gtfs, &custom_rules)
+            .iter()
+            .any(|i| i.issue_type == IssueType::ServiceDrop)
+    );
+}
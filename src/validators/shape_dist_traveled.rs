@@ -0,0 +1,314 @@
+#[cfg(test)]
+use super::utils::issues_of_type;
+use crate::issues::{Issue, IssueType, Severity};
+use geo::{Distance as _, Haversine};
+use gtfs_structures::{ObjectType, Shape, Stop};
+
+/// Distance in meters between two consecutive points of a shape from which the shape is considered to jump
+const MAX_SHAPE_POINTS_DISTANCE: f64 = 10_000.;
+/// Factor between the distance units of a shape and of most shapes, or of a trip and its shape,
+/// from which they are considered inconsistent
+const MAX_UNIT_FACTOR: f64 = 10.;
+/// Length in meters under which a shape is too short to compare the unit of its distances
+const MIN_UNIT_LENGTH: f64 = 100.;
+/// Tolerance on the shape_dist_traveled of the stop times over the length of the shape, for rounding errors
+const EXCEED_TOLERANCE: f32 = 1.01;
+
+fn valid_coord(point: &Shape) -> bool {
+    (point.latitude != 0.0 || point.longitude != 0.0)
+        && (-90.0..=90.0).contains(&point.latitude)
+        && (-180.0..=180.0).contains(&point.longitude)
+}
+
+fn distance(a: &Shape, b: &Shape) -> f64 {
+    Haversine.distance(
+        geo::Point::new(a.longitude, a.latitude),
+        geo::Point::new(b.longitude, b.latitude),
+    )
+}
+
+fn shape_issue(severity: Severity, issue_type: IssueType, shape_id: &str) -> Issue {
+    Issue::new(severity, issue_type, shape_id).object_type(ObjectType::Shape)
+}
+
+fn check_shape(shape_id: &str, shape: &[Shape]) -> Vec<Issue> {
+    let mut issues = vec![];
+    if let Some(w) = shape.windows(2).find(|w| w[0].sequence == w[1].sequence) {
+        issues.push(
+            shape_issue(Severity::Error, IssueType::DuplicateShapeSequence, shape_id).details(
+                &format!("Several points have the sequence {}", w[0].sequence),
+            ),
+        );
+    }
+    if let Some(w) = shape
+        .windows(2)
+        .find(|w| match (w[0].dist_traveled, w[1].dist_traveled) {
+            (Some(previous), Some(next)) => next < previous,
+            _ => false,
+        })
+    {
+        issues.push(
+            shape_issue(
+                Severity::Error,
+                IssueType::DecreasingShapeDistTraveled,
+                shape_id,
+            )
+            .details(&format!(
+                "The shape_dist_traveled decreases from {} to {} at the sequence {}",
+                w[0].dist_traveled.unwrap_or_default(),
+                w[1].dist_traveled.unwrap_or_default(),
+                w[1].sequence
+            )),
+        );
+    }
+    if shape.iter().all(valid_coord)
+        && let Some((w, jump)) = shape
+            .windows(2)
+            .map(|w| (w, distance(&w[0], &w[1])))
+            .find(|(_, d)| *d > MAX_SHAPE_POINTS_DISTANCE)
+    {
+        issues.push(
+            shape_issue(Severity::Warning, IssueType::ShapeJump, shape_id).details(&format!(
+                "The points of sequence {} and {} are {:.0} meters apart",
+                w[0].sequence, w[1].sequence, jump
+            )),
+        );
+    }
+    issues
+}
+
+// The length in meters of the shape, when it is long enough to compare the unit of its distances
+fn length(shape: &[Shape]) -> Option<f64> {
+    if !shape.iter().all(valid_coord) {
+        return None;
+    }
+    let length: f64 = shape.windows(2).map(|w| distance(&w[0], &w[1])).sum();
+    (length >= MIN_UNIT_LENGTH).then_some(length)
+}
+
+// How much the shape_dist_traveled grows for each meter along the shape
+fn unit_ratio(shape: &[Shape]) -> Option<f64> {
+    let first = shape.first()?.dist_traveled?;
+    let last = shape.last()?.dist_traveled?;
+    if last <= first {
+        return None;
+    }
+    Some(f64::from(last - first) / length(shape)?)
+}
+
+fn same_unit(ratio: f64) -> bool {
+    ratio <= MAX_UNIT_FACTOR && ratio * MAX_UNIT_FACTOR >= 1.
+}
+
+// The distance units are free, but must be the same for all the shapes
+fn check_units(gtfs: &gtfs_structures::Gtfs) -> Vec<Issue> {
+    let ratios: Vec<(&String, f64)> = gtfs
+        .shapes
+        .iter()
+        .filter_map(|(id, shape)| Some((id, unit_ratio(shape)?)))
+        .collect();
+    let mut sorted: Vec<f64> = ratios.iter().map(|(_, ratio)| *ratio).collect();
+    sorted.sort_by(f64::total_cmp);
+    let Some(median) = sorted.get(sorted.len() / 2).copied() else {
+        return vec![];
+    };
+    ratios
+        .into_iter()
+        .filter(|(_, ratio)| !same_unit(ratio / median))
+        .map(|(id, ratio)| {
+            shape_issue(Severity::Warning, IssueType::InconsistentShapeDistTraveled, id)
+                .details(&format!(
+                    "The shape_dist_traveled grows by {:.4} per meter along the shape, while it grows by {:.4} for most shapes",
+                    ratio, median
+                ))
+        })
+        .collect()
+}
+
+// The index of the shape point closest to the stop, from the given one
+fn closest_point(shape: &[Shape], from: usize, stop: &Stop) -> Option<usize> {
+    let stop = geo::Point::new(stop.longitude?, stop.latitude?);
+    let distance = |s: &Shape| Haversine.distance(geo::Point::new(s.longitude, s.latitude), stop);
+    shape
+        .iter()
+        .enumerate()
+        .skip(from)
+        .min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)))
+        .map(|(index, _)| index)
+}
+
+// How much the shape_dist_traveled grows between the first and last stops of the trip,
+// for the stop times and for the shape, as a trip can use only a part of its shape
+fn covered_spans(trip: &gtfs_structures::Trip, shape: &[Shape]) -> Option<(f32, f32)> {
+    if !shape.iter().all(valid_coord) {
+        return None;
+    }
+    let first = trip
+        .stop_times
+        .iter()
+        .find(|st| st.shape_dist_traveled.is_some())?;
+    let last = trip
+        .stop_times
+        .iter()
+        .rfind(|st| st.shape_dist_traveled.is_some())?;
+    let from = closest_point(shape, 0, &first.stop)?;
+    let to = closest_point(shape, from, &last.stop)?;
+    let length: f64 = shape[from..=to]
+        .windows(2)
+        .map(|w| distance(&w[0], &w[1]))
+        .sum();
+    if length < MIN_UNIT_LENGTH {
+        return None;
+    }
+    let trip_span = last.shape_dist_traveled? - first.shape_dist_traveled?;
+    let shape_span = shape[to].dist_traveled? - shape[from].dist_traveled?;
+    (trip_span > 0. && shape_span > 0.).then_some((trip_span, shape_span))
+}
+
+fn check_trip(trip: &gtfs_structures::Trip, shape: Option<&Vec<Shape>>) -> Vec<Issue> {
+    let mut issues = vec![];
+    let distances: Vec<(u32, f32)> = trip
+        .stop_times
+        .iter()
+        .filter_map(|st| Some((st.stop_sequence, st.shape_dist_traveled?)))
+        .collect();
+    if let Some(w) = distances.windows(2).find(|w| w[1].1 < w[0].1) {
+        issues.push(
+            Issue::new_with_obj(
+                Severity::Error,
+                IssueType::DecreasingShapeDistTraveled,
+                trip,
+            )
+            .details(&format!(
+                "The shape_dist_traveled decreases from {} to {} at the stop sequence {}",
+                w[0].1, w[1].1, w[1].0
+            )),
+        );
+    }
+    let shape_max = shape
+        .into_iter()
+        .flatten()
+        .filter_map(|s| s.dist_traveled)
+        .reduce(f32::max);
+    // the stop times and the part of the shape they cover must be in the same unit
+    if let Some((trip_span, shape_span)) = shape.and_then(|shape| covered_spans(trip, shape))
+        && !same_unit(f64::from(trip_span) / f64::from(shape_span))
+    {
+        issues.push(
+            Issue::new_with_obj(
+                Severity::Warning,
+                IssueType::InconsistentShapeDistTraveled,
+                trip,
+            )
+            .details(&format!(
+                "The shape_dist_traveled of the stop times grows by {}, while the shape grows by {} between the same stops",
+                trip_span, shape_span
+            )),
+        );
+    }
+    if let Some(shape_max) = shape_max
+        && let Some((sequence, dist)) = distances
+            .iter()
+            .find(|(_, dist)| *dist > shape_max * EXCEED_TOLERANCE)
+    {
+        issues.push(
+            Issue::new_with_obj(
+                Severity::Error,
+                IssueType::ShapeDistTraveledExceedsShape,
+                trip,
+            )
+            .details(&format!(
+                "The shape_dist_traveled of the stop sequence {} is {}, while the shape ends at {}",
+                sequence, dist, shape_max
+            )),
+        );
+    }
+    issues
+}
+
+/// Check the shape_dist_traveled of the shapes and the stop times, and the sequences of the shape points
+pub fn validate(gtfs: &gtfs_structures::Gtfs) -> Vec<Issue> {
+    let shape_issues = gtfs
+        .shapes
+        .iter()
+        .flat_map(|(id, shape)| check_shape(id, shape));
+    let trip_issues = gtfs.trips.values().flat_map(|trip| {
+        check_trip(
+            trip,
+            trip.shape_id.as_ref().and_then(|id| gtfs.shapes.get(id)),
+        )
+    });
+    shape_issues
+        .chain(check_units(gtfs))
+        .chain(trip_issues)
+        .collect()
+}
+
+#[test]
+fn test_decreasing() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/shape_dist_traveled").unwrap();
+    let issues = issues_of_type(validate(&gtfs), IssueType::DecreasingShapeDistTraveled);
+
+    assert_eq!(2, issues.len());
+    assert_eq!("DECREASING", issues[0].object_id);
+    assert_eq!(Some(ObjectType::Shape), issues[0].object_type);
+    assert_eq!(
+        Some("The shape_dist_traveled decreases from 2000 to 1500 at the sequence 3".to_owned()),
+        issues[0].details
+    );
+    assert_eq!("T_DECREASING", issues[1].object_id);
+    assert_eq!(Some(ObjectType::Trip), issues[1].object_type);
+}
+
+#[test]
+fn test_exceeds_shape() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/shape_dist_traveled").unwrap();
+    let issues = issues_of_type(validate(&gtfs), IssueType::ShapeDistTraveledExceedsShape);
+
+    assert_eq!(1, issues.len());
+    assert_eq!("T_EXCEEDS", issues[0].object_id);
+    assert_eq!(
+        Some(
+            "The shape_dist_traveled of the stop sequence 2 is 3500, while the shape ends at 3336"
+                .to_owned()
+        ),
+        issues[0].details
+    );
+}
+
+#[test]
+fn test_inconsistent_unit() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/shape_dist_traveled").unwrap();
+    let issues = issues_of_type(validate(&gtfs), IssueType::InconsistentShapeDistTraveled);
+
+    // a shape and the stop times of a trip in kilometers, while the others are in meters,
+    // the trips using only a part of their shape being in meters too
+    assert_eq!(2, issues.len());
+    assert_eq!("KM", issues[0].object_id);
+    assert_eq!(
+        Some("The shape_dist_traveled grows by 0.0010 per meter along the shape, while it grows by 1.0000 for most shapes".to_owned()),
+        issues[0].details
+    );
+    assert_eq!("T_KM", issues[1].object_id);
+    assert_eq!(Some(ObjectType::Trip), issues[1].object_type);
+
+    // a feed in kilometers, consistently, has no issue
+    let gtfs = gtfs_structures::Gtfs::new("test_data/shape_dist_traveled_km").unwrap();
+    assert!(issues_of_type(validate(&gtfs), IssueType::InconsistentShapeDistTraveled).is_empty());
+}
+
+#[test]
+fn test_duplicate_sequence_and_jump() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/shape_dist_traveled").unwrap();
+    let issues = issues_of_type(validate(&gtfs), IssueType::DuplicateShapeSequence);
+    assert_eq!(1, issues.len());
+    assert_eq!("DUPLICATE", issues[0].object_id);
+
+    let issues = issues_of_type(validate(&gtfs), IssueType::ShapeJump);
+    assert_eq!(1, issues.len());
+    assert_eq!("JUMP", issues[0].object_id);
+    assert_eq!(
+        Some("The points of sequence 2 and 3 are 111195 meters apart".to_owned()),
+        issues[0].details
+    );
+}
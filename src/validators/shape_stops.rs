@@ -0,0 +1,255 @@
+#[cfg(test)]
+use super::utils::issues_of_type;
+use crate::custom_rules::CustomRules;
+use crate::issues::{Issue, IssueType, RelatedObject, Severity};
+use geo::{Closest, ClosestPoint, Distance as _, Haversine};
+use gtfs_structures::{ObjectType, Stop, Trip};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Distance in meters from which a stop is considered too far from the shape of its trip
+const MAX_STOP_SHAPE_DISTANCE: f64 = 100.;
+
+// A point projected on the shape, with the index of the segment it is projected on
+struct Projection {
+    segment: usize,
+    distance: f64,
+}
+
+fn point(stop: &Stop) -> Option<geo::Point> {
+    Some(geo::Point::new(stop.longitude?, stop.latitude?))
+}
+
+fn project(segment: &geo::Line, index: usize, point: geo::Point) -> Projection {
+    let closest = match segment.closest_point(&point) {
+        Closest::Intersection(p) | Closest::SinglePoint(p) => p,
+        Closest::Indeterminate => segment.start_point(),
+    };
+    Projection {
+        segment: index,
+        distance: Haversine.distance(point, closest),
+    }
+}
+
+// The first part of the shape close enough to the stop, from the given segment
+fn project_forward(
+    segments: &[geo::Line],
+    from: usize,
+    point: geo::Point,
+    max_distance: f64,
+) -> Option<Projection> {
+    let mut projections = segments
+        .iter()
+        .enumerate()
+        .skip(from)
+        .map(|(index, segment)| project(segment, index, point))
+        .skip_while(|p| p.distance > max_distance);
+    let mut best = projections.next()?;
+    // when the shape runs along the stop on several segments, the closest one is kept
+    for projection in projections.take_while(|p| p.distance <= max_distance) {
+        if projection.distance < best.distance {
+            best = projection;
+        }
+    }
+    Some(best)
+}
+
+fn stop_issue(issue_type: IssueType, stop: &Stop, shape_id: &str, trip: &Trip) -> Issue {
+    let mut issue =
+        Issue::new_with_obj(Severity::Warning, issue_type, stop).add_related_object(trip);
+    issue.related_objects.push(RelatedObject {
+        id: shape_id.to_owned(),
+        object_type: Some(ObjectType::Shape),
+        name: None,
+    });
+    issue
+}
+
+fn check_trip(
+    trip: &Trip,
+    shape_id: &str,
+    shape: &[gtfs_structures::Shape],
+    stops: &[&Arc<Stop>],
+    max_distance: f64,
+) -> Vec<Issue> {
+    let segments: Vec<geo::Line> = shape
+        .windows(2)
+        .map(|w| {
+            geo::Line::new(
+                geo::coord! { x: w[0].longitude, y: w[0].latitude },
+                geo::coord! { x: w[1].longitude, y: w[1].latitude },
+            )
+        })
+        .collect();
+    let mut issues = vec![];
+
+    let mut segment = 0;
+    for stop in stops {
+        let Some(point) = point(stop) else {
+            continue;
+        };
+        if let Some(projection) = project_forward(&segments, segment, point, max_distance) {
+            segment = projection.segment;
+            continue;
+        }
+        let closest = segments
+            .iter()
+            .enumerate()
+            .map(|(index, segment)| project(segment, index, point).distance)
+            .fold(f64::INFINITY, f64::min);
+        let issue = if closest <= max_distance {
+            stop_issue(IssueType::StopOutOfOrderOnShape, stop, shape_id, trip).details(
+                "Along the shape of the trip, the stop is before the stop that precedes it",
+            )
+        } else {
+            stop_issue(IssueType::StopTooFarFromShape, stop, shape_id, trip).details(&format!(
+                "The stop is {:.0} meters away from the shape of the trip",
+                closest
+            ))
+        };
+        issues.push(issue);
+    }
+
+    let endpoints = [
+        ("first", stops.first(), shape.first()),
+        ("last", stops.last(), shape.last()),
+    ];
+    for (end, stop, shape_point) in endpoints {
+        if let (Some(stop), Some(shape_point)) = (stop, shape_point)
+            && let Some(point) = point(stop)
+        {
+            let distance = Haversine.distance(
+                point,
+                geo::Point::new(shape_point.longitude, shape_point.latitude),
+            );
+            if distance > max_distance {
+                let issue = Issue::new(
+                    Severity::Information,
+                    IssueType::ShapeEndpointTooFarFromStop,
+                    shape_id,
+                )
+                .object_type(ObjectType::Shape)
+                .add_related_object(&***stop)
+                .add_related_object(trip)
+                .details(&format!(
+                    "The {} point of the shape is {:.0} meters away from the {} stop of the trip",
+                    end, distance, end
+                ));
+                issues.push(issue);
+            }
+        }
+    }
+    issues
+}
+
+/// Check that the stops of the trips are along their shape, in the right order,
+/// and that the shapes start and end at the first and last stops
+pub fn validate(gtfs: &gtfs_structures::Gtfs, custom_rules: &CustomRules) -> Vec<Issue> {
+    let max_distance = custom_rules
+        .max_stop_shape_distance
+        .unwrap_or(MAX_STOP_SHAPE_DISTANCE);
+
+    // the trips sharing a shape and their stops are only checked once
+    let mut checked = HashSet::new();
+    let mut trips: Vec<&Trip> = gtfs.trips.values().collect();
+    trips.sort_by(|a, b| a.id.cmp(&b.id));
+
+    let mut seen = HashSet::new();
+    let mut issues = vec![];
+    for trip in trips {
+        let Some(shape_id) = trip.shape_id.as_deref() else {
+            continue;
+        };
+        let Some(shape) = gtfs.shapes.get(shape_id).filter(|s| s.len() >= 2) else {
+            continue;
+        };
+        let stops: Vec<&Arc<Stop>> = trip.stop_times.iter().map(|st| &st.stop).collect();
+        if !checked.insert((
+            shape_id,
+            stops.iter().map(|s| s.id.as_str()).collect::<Vec<_>>(),
+        )) {
+            continue;
+        }
+        for issue in check_trip(trip, shape_id, shape, &stops, max_distance) {
+            if seen.insert((
+                issue.issue_type,
+                issue.object_id.clone(),
+                shape_id,
+                issue.details.clone(),
+            )) {
+                issues.push(issue);
+            }
+        }
+    }
+    issues
+}
+
+#[test]
+fn test_stop_too_far() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/shape_stops").unwrap();
+    let issues = issues_of_type(
+        validate(&gtfs, &CustomRules::default()),
+        IssueType::StopTooFarFromShape,
+    );
+
+    assert_eq!(1, issues.len());
+    assert_eq!("C", issues[0].object_id);
+    assert_eq!(Some(ObjectType::Stop), issues[0].object_type);
+    assert_eq!(
+        Some("The stop is 889 meters away from the shape of the trip".to_owned()),
+        issues[0].details
+    );
+    assert_eq!(
+        vec!["T1", "S1"],
+        issues[0]
+            .related_objects
+            .iter()
+            .map(|o| o.id.as_str())
+            .collect::<Vec<_>>()
+    );
+
+    // with a greater distance, the stop is close enough
+    let gtfs = gtfs_structures::Gtfs::new("test_data/shape_stops").unwrap();
+    let custom_rules = CustomRules {
+        max_stop_shape_distance: Some(1000.),
+        ..Default::default()
+    };
+    assert!(
+        !validate(&gtfs, &custom_rules)
+            .iter()
+            .any(|i| i.issue_type == IssueType::StopTooFarFromShape)
+    );
+}
+
+#[test]
+fn test_stop_out_of_order() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/shape_stops").unwrap();
+    let issues = issues_of_type(
+        validate(&gtfs, &CustomRules::default()),
+        IssueType::StopOutOfOrderOnShape,
+    );
+
+    assert_eq!(1, issues.len());
+    assert_eq!("B", issues[0].object_id);
+    assert_eq!("T2", issues[0].related_objects[0].id);
+}
+
+#[test]
+fn test_shape_endpoint() {
+    let gtfs = gtfs_structures::Gtfs::new("test_data/shape_stops").unwrap();
+    let issues = issues_of_type(
+        validate(&gtfs, &CustomRules::default()),
+        IssueType::ShapeEndpointTooFarFromStop,
+    );
+
+    assert_eq!(1, issues.len());
+    assert_eq!("S2", issues[0].object_id);
+    assert_eq!(Some(ObjectType::Shape), issues[0].object_type);
+    assert_eq!(
+        Some(
+            "The last point of the shape is 2224 meters away from the last stop of the trip"
+                .to_owned()
+        ),
+        issues[0].details
+    );
+}
@@ -0,0 +1,305 @@
+use super::utils::dedup_by_object;
+#[cfg(test)]
+use super::utils::issues_of_type;
+use crate::extra_files::{ExtraFiles, Transfer};
+use crate::issues::{Issue, IssueType, Severity};
+use geo::{Distance as _, Haversine};
+use gtfs_structures::{ObjectType, Stop, TransferType};
+use std::collections::{HashMap, HashSet};
+
+/// Above two kilometers, walking between two stops is not a transfer anymore
+const MAX_DISTANCE: f64 = 2_000.;
+/// In meters per second, the speed of someone running
+const MAX_WALKING_SPEED: f64 = 2.5;
+/// In meters per second, the speed of someone walking very slowly
+const MIN_WALKING_SPEED: f64 = 0.5;
+/// In seconds, what can be added to the walk (stairs, elevators, security checks…)
+const TRANSFER_MARGIN: f64 = 600.;
+
+struct Ids<'a> {
+    stops: Option<HashMap<&'a str, &'a Stop>>,
+    routes: Option<HashSet<&'a str>>,
+    trips: Option<HashSet<&'a str>>,
+}
+
+impl<'a> Ids<'a> {
+    fn new(raw_gtfs: &'a gtfs_structures::RawGtfs) -> Self {
+        Ids {
+            stops: raw_gtfs
+                .stops
+                .as_ref()
+                .ok()
+                .map(|stops| stops.iter().map(|s| (s.id.as_str(), s)).collect()),
+            routes: raw_gtfs
+                .routes
+                .as_ref()
+                .ok()
+                .map(|routes| routes.iter().map(|r| r.id.as_str()).collect()),
+            trips: raw_gtfs
+                .trips
+                .as_ref()
+                .ok()
+                .map(|trips| trips.iter().map(|t| t.id.as_str()).collect()),
+        }
+    }
+
+    fn stop(&self, id: Option<&str>) -> Option<&'a Stop> {
+        self.stops.as_ref()?.get(id?).copied()
+    }
+
+    fn check_ref(&self, id: &Option<String>, object_type: ObjectType) -> Option<Issue> {
+        let id = id.as_deref()?;
+        let known = match object_type {
+            ObjectType::Stop => self.stops.as_ref().map(|ids| ids.contains_key(id)),
+            ObjectType::Route => self.routes.as_ref().map(|ids| ids.contains(id)),
+            _ => self.trips.as_ref().map(|ids| ids.contains(id)),
+        }?;
+        if known {
+            None
+        } else {
+            let details = format!(
+                "The {} is referenced by a transfer but does not exist",
+                format!("{:?}", object_type).to_lowercase()
+            );
+            Some(
+                Issue::new(Severity::Fatal, IssueType::InvalidReference, id)
+                    .object_type(object_type)
+                    .details(&details),
+            )
+        }
+    }
+}
+
+fn side(stop: &Option<String>, route: &Option<String>, trip: &Option<String>) -> String {
+    [("stop", stop), ("route", route), ("trip", trip)]
+        .iter()
+        .filter_map(|(label, id)| id.as_ref().map(|id| format!("{} {}", label, id)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe(t: &Transfer) -> String {
+    format!(
+        "The transfer from {} to {}",
+        side(&t.from_stop_id, &t.from_route_id, &t.from_trip_id),
+        side(&t.to_stop_id, &t.to_route_id, &t.to_trip_id)
+    )
+}
+
+// The issue is about the stop from which the transfer starts, or the trip for in-seat transfers
+fn transfer_issue(severity: Severity, issue_type: IssueType, t: &Transfer, ids: &Ids) -> Issue {
+    let mut issue = match (ids.stop(t.from_stop_id.as_deref()), &t.from_stop_id) {
+        (Some(stop), _) => Issue::new_with_obj(severity, issue_type, stop),
+        (None, Some(stop_id)) => {
+            Issue::new(severity, issue_type, stop_id).object_type(ObjectType::Stop)
+        }
+        (None, None) => Issue::new(
+            severity,
+            issue_type,
+            t.from_trip_id.as_deref().unwrap_or(""),
+        )
+        .object_type(ObjectType::Trip),
+    };
+    if let Some(stop) = ids.stop(t.to_stop_id.as_deref()) {
+        issue.push_related_object(stop);
+    }
+    issue
+}
+
+fn check_references(transfers: &[Transfer], ids: &Ids) -> Vec<Issue> {
+    let issues = transfers
+        .iter()
+        .flat_map(|t| {
+            [
+                ids.check_ref(&t.from_stop_id, ObjectType::Stop),
+                ids.check_ref(&t.to_stop_id, ObjectType::Stop),
+                ids.check_ref(&t.from_route_id, ObjectType::Route),
+                ids.check_ref(&t.to_route_id, ObjectType::Route),
+                ids.check_ref(&t.from_trip_id, ObjectType::Trip),
+                ids.check_ref(&t.to_trip_id, ObjectType::Trip),
+            ]
+        })
+        .flatten();
+    dedup_by_object(issues)
+}
+
+// The fields required depend on the type of the transfer
+fn check_type(t: &Transfer) -> Option<&'static str> {
+    match t.transfer_type {
+        TransferType::StayOnBoard | TransferType::MustAlight
+            if t.from_trip_id.is_none() || t.to_trip_id.is_none() =>
+        {
+            Some("an in-seat transfer (transfer_type 4 or 5) requires from_trip_id and to_trip_id")
+        }
+        TransferType::Timed | TransferType::MinTime | TransferType::Impossible
+            if t.from_stop_id.is_none() || t.to_stop_id.is_none() =>
+        {
+            Some("a transfer of transfer_type 1, 2 or 3 requires from_stop_id and to_stop_id")
+        }
+        TransferType::MinTime if t.min_transfer_time.is_none() => {
+            Some("a transfer of transfer_type 2 requires a min_transfer_time")
+        }
+        _ => None,
+    }
+}
+
+// The walk between the two stops of the transfer, compared to the distance between them
+fn check_walk(t: &Transfer, ids: &Ids) -> Option<String> {
+    if t.transfer_type == TransferType::Impossible || t.from_stop_id == t.to_stop_id {
+        return None;
+    }
+    let from = ids.stop(t.from_stop_id.as_deref())?;
+    let to = ids.stop(t.to_stop_id.as_deref())?;
+    let distance = Haversine.distance(
+        geo::Point::new(from.longitude?, from.latitude?),
+        geo::Point::new(to.longitude?, to.latitude?),
+    );
+    if distance > MAX_DISTANCE {
+        return Some(format!("the stops are {:.0} meters apart", distance));
+    }
+    let duration = f64::from(t.min_transfer_time?);
+    if distance > duration * MAX_WALKING_SPEED {
+        Some(format!(
+            "{:.0} meters can't be walked in {} seconds",
+            distance, duration
+        ))
+    } else if duration > distance / MIN_WALKING_SPEED + TRANSFER_MARGIN {
+        Some(format!(
+            "{} seconds is very long to walk {:.0} meters",
+            duration, distance
+        ))
+    } else {
+        None
+    }
+}
+
+// The same stops, routes and trips can only be given once
+fn check_duplicates<'a>(
+    transfers: &'a [Transfer],
+    ids: &'a Ids,
+) -> impl Iterator<Item = Issue> + 'a {
+    let mut keys = HashSet::new();
+    let mut duplicates = HashSet::new();
+    transfers.iter().filter_map(move |t| {
+        let key = (
+            &t.from_stop_id,
+            &t.to_stop_id,
+            &t.from_route_id,
+            &t.to_route_id,
+            &t.from_trip_id,
+            &t.to_trip_id,
+        );
+        if keys.insert(key) || !duplicates.insert(key) {
+            None
+        } else {
+            Some(
+                transfer_issue(Severity::Error, IssueType::DuplicateTransfer, t, ids)
+                    .details(&format!("{} is given several times", describe(t))),
+            )
+        }
+    })
+}
+
+/// Check the transfers, read with their route and trip ids
+pub fn validate(raw_gtfs: &gtfs_structures::RawGtfs, extra_files: &ExtraFiles) -> Vec<Issue> {
+    let transfers = match &extra_files.transfers {
+        Some(Ok(transfers)) => transfers,
+        _ => return vec![],
+    };
+    let ids = Ids::new(raw_gtfs);
+
+    check_references(transfers, &ids)
+        .into_iter()
+        .chain(transfers.iter().filter_map(|t| {
+            check_type(t).map(|reason| {
+                transfer_issue(Severity::Error, IssueType::InvalidTransfer, t, &ids)
+                    .details(&format!("{}: {}", describe(t), reason))
+            })
+        }))
+        .chain(transfers.iter().filter_map(|t| {
+            check_walk(t, &ids).map(|reason| {
+                transfer_issue(Severity::Warning, IssueType::ImplausibleTransfer, t, &ids)
+                    .details(&format!("{}: {}", describe(t), reason))
+            })
+        }))
+        .chain(check_duplicates(transfers, &ids))
+        .collect()
+}
+
+// The issues of the given type found in the test data
+#[cfg(test)]
+fn issues(issue_type: IssueType) -> Vec<Issue> {
+    let raw_gtfs = gtfs_structures::RawGtfs::new("test_data/transfers").unwrap();
+    let extra_files = ExtraFiles::from_path("test_data/transfers");
+    issues_of_type(validate(&raw_gtfs, &extra_files), issue_type)
+}
+
+#[test]
+fn test_references() {
+    let issues = issues(IssueType::InvalidReference);
+    let references = issues
+        .iter()
+        .map(|i| (i.object_id.as_str(), &i.object_type))
+        .collect::<HashSet<_>>();
+
+    assert_eq!(
+        HashSet::from([
+            ("unknown_stop", &Some(ObjectType::Stop)),
+            ("unknown_route", &Some(ObjectType::Route)),
+            ("unknown_trip", &Some(ObjectType::Trip)),
+        ]),
+        references
+    );
+}
+
+#[test]
+fn test_transfer_type() {
+    let invalid = issues(IssueType::InvalidTransfer);
+
+    assert_eq!(3, invalid.len());
+    assert_eq!(
+        Some("The transfer from stop A, trip AB1 to stop B: an in-seat transfer (transfer_type 4 or 5) requires from_trip_id and to_trip_id".to_owned()),
+        invalid[0].details
+    );
+    assert_eq!(
+        Some("The transfer from stop B to stop A: a transfer of transfer_type 2 requires a min_transfer_time".to_owned()),
+        invalid[1].details
+    );
+    assert_eq!(
+        Some("The transfer from stop C to : a transfer of transfer_type 1, 2 or 3 requires from_stop_id and to_stop_id".to_owned()),
+        invalid[2].details
+    );
+    assert_eq!("B", invalid[1].object_id);
+    assert_eq!(Some(ObjectType::Stop), invalid[1].object_type);
+    assert_eq!("A", invalid[1].related_objects[0].id);
+}
+
+#[test]
+fn test_implausible_transfer() {
+    let issues = issues(IssueType::ImplausibleTransfer);
+    let implausible = issues
+        .iter()
+        .map(|i| i.details.as_deref().unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        vec![
+            "The transfer from stop A to stop C: 445 meters can't be walked in 30 seconds",
+            "The transfer from stop A to stop FAR: the stops are 8896 meters apart",
+            "The transfer from stop B to stop C: 7200 seconds is very long to walk 345 meters",
+        ],
+        implausible
+    );
+}
+
+#[test]
+fn test_duplicate_transfer() {
+    let duplicates = issues(IssueType::DuplicateTransfer);
+
+    assert_eq!(1, duplicates.len());
+    assert_eq!("A", duplicates[0].object_id);
+    assert_eq!(
+        Some("The transfer from stop A to stop B is given several times".to_owned()),
+        duplicates[0].details
+    );
+}
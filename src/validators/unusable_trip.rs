@@ -2,12 +2,23 @@ use std::collections::HashSet;
 
 use gtfs_structures::Trip;
 
+use crate::extra_files::ExtraFiles;
 use crate::issues::Issue;
 use crate::{IssueType, Severity};
 
-pub fn validate(gtfs: &gtfs_structures::Gtfs) -> Vec<Issue> {
+pub fn validate(gtfs: &gtfs_structures::Gtfs, extra_files: &ExtraFiles) -> Vec<Issue> {
+    // a trip at a location or a location group can be used with a single stop time
+    let flex_trips: HashSet<&str> = match &extra_files.flex_stop_times {
+        Some(Ok(stop_times)) => stop_times
+            .iter()
+            .filter(|st| st.is_flex())
+            .map(|st| st.trip_id.as_str())
+            .collect(),
+        _ => HashSet::new(),
+    };
     gtfs.trips
         .values()
+        .filter(|trip| !flex_trips.contains(trip.id.as_str()))
         .filter_map(|trip| {
             let mut stops = HashSet::new();
             for stop_time in &trip.stop_times {
@@ -32,7 +43,7 @@ fn mk_issue(trip: &Trip) -> Issue {
 #[test]
 fn test() {
     let gtfs = gtfs_structures::Gtfs::new("test_data/unusable_trip").unwrap();
-    let issues = validate(&gtfs);
+    let issues = validate(&gtfs, &ExtraFiles::default());
 
     assert_eq!(1, issues.len());
     assert_eq!("AB1", issues[0].object_id);
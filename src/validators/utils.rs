@@ -0,0 +1,43 @@
+//! Helpers shared by the validators.
+use crate::issues::Issue;
+use chrono::NaiveDate;
+use std::collections::HashSet;
+
+/// Keeps one issue by object and details, as an invalid reference can be repeated many times.
+pub fn dedup_by_object(issues: impl IntoIterator<Item = Issue>) -> Vec<Issue> {
+    let mut seen = HashSet::new();
+    issues
+        .into_iter()
+        .filter(|i| seen.insert((i.object_id.clone(), i.details.clone())))
+        .collect()
+}
+
+/// The first date of the calendar and the calendar dates, from which the days of the trips are counted.
+pub fn feed_start(gtfs: &gtfs_structures::Gtfs) -> Option<NaiveDate> {
+    gtfs.calendar
+        .values()
+        .map(|c| c.start_date)
+        .chain(gtfs.calendar_dates.values().flatten().map(|d| d.date))
+        .min()
+}
+
+/// A time of a trip, in seconds, formatted as HH:MM:SS.
+pub fn format_time(time: u32) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// The issues of a type, sorted by object and details, to be compared in the tests.
+#[cfg(test)]
+pub fn issues_of_type(issues: Vec<Issue>, issue_type: crate::issues::IssueType) -> Vec<Issue> {
+    let mut issues: Vec<Issue> = issues
+        .into_iter()
+        .filter(|i| i.issue_type == issue_type)
+        .collect();
+    issues.sort_by(|a, b| (&a.object_id, &a.details).cmp(&(&b.object_id, &b.details)));
+    issues
+}
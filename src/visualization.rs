@@ -22,7 +22,14 @@ pub fn generate_issue_visualization(
                 geojson_feature_line_string(&stop_id, related_stop, gtfs, issue)
             });
 
-            let features = stop_features.chain(line_string_features).collect();
+            let shape_features = get_related_ids(issue, ObjectType::Shape)
+                .into_iter()
+                .flat_map(|shape_id| geojson_feature_shape(&shape_id, gtfs));
+
+            let features = stop_features
+                .chain(line_string_features)
+                .chain(shape_features)
+                .collect();
 
             let feature_collection = FeatureCollection {
                 bbox: None,
@@ -32,10 +39,45 @@ pub fn generate_issue_visualization(
 
             Some(feature_collection)
         }
+        Some(ObjectType::Shape) => {
+            let stop_features = get_related_stop_ids(issue)
+                .into_iter()
+                .flat_map(|stop_id| geojson_feature_point(&stop_id, gtfs));
+
+            let features = geojson_feature_shape(&issue.object_id, gtfs)
+                .into_iter()
+                .chain(stop_features)
+                .collect();
+
+            Some(FeatureCollection {
+                bbox: None,
+                features,
+                foreign_members: None,
+            })
+        }
         _ => None,
     }
 }
 
+fn geojson_feature_shape(shape_id: &str, gtfs: &Gtfs) -> Option<Feature> {
+    gtfs.shapes.get(shape_id).map(|shape| {
+        let mut properties = Map::new();
+        properties.insert(String::from("shape_id"), serde_json::json!(shape_id));
+
+        Feature {
+            geometry: Some(geojson::Geometry::new_line_string(
+                shape
+                    .iter()
+                    .map(|point| vec![point.longitude, point.latitude]),
+            )),
+            bbox: None,
+            properties: Some(properties),
+            id: None,
+            foreign_members: None,
+        }
+    })
+}
+
 fn geojson_feature_point(stop_id: &str, gtfs: &Gtfs) -> Option<Feature> {
     gtfs.stops.get(stop_id).map(|stop| {
         let mut properties = Map::new();
@@ -67,10 +109,14 @@ fn get_stop_geom(stop: &Arc<gtfs_structures::Stop>) -> Option<geojson::Geometry>
 }
 
 fn get_related_stop_ids(issue: &issues::Issue) -> Vec<String> {
+    get_related_ids(issue, ObjectType::Stop)
+}
+
+fn get_related_ids(issue: &issues::Issue, object_type: ObjectType) -> Vec<String> {
     let related_objects = &issue.related_objects;
     related_objects
         .iter()
-        .filter(|o| o.object_type == Some(ObjectType::Stop))
+        .filter(|o| o.object_type.as_ref() == Some(&object_type))
         .map(|s| s.id.clone())
         .collect()
 }
@@ -161,3 +207,36 @@ fn test_generated_geojson() {
         "{\"type\":\"FeatureCollection\",\"features\":[{\"type\":\"Feature\",\"geometry\":{\"type\":\"Point\",\"coordinates\":[2.449186,48.796058]},\"properties\":{\"id\":\"near1\",\"name\":\"Near1\"}},{\"type\":\"Feature\",\"geometry\":{\"type\":\"Point\",\"coordinates\":[0.0,0.0]},\"properties\":{\"id\":\"null\",\"name\":\"Null Island\"}},{\"type\":\"Feature\",\"geometry\":{\"type\":\"LineString\",\"coordinates\":[[2.449186,48.796058],[0.0,0.0]]},\"properties\":{\"details\":\"computed speed between the stops is 325858.52 km/h (5430975 m travelled in 60 seconds)\"}}]}"
     );
 }
+
+#[test]
+fn test_generated_shape_geojson() {
+    use crate::custom_rules;
+    use crate::issues;
+    use crate::validate;
+
+    let custom_rules = custom_rules::CustomRules {
+        ..Default::default()
+    };
+    let validation = validate::generate_validation("test_data/shape_stops", 10, &custom_rules);
+    let far_stop_issues = validation
+        .validations
+        .get(&issues::IssueType::StopTooFarFromShape)
+        .unwrap();
+
+    assert_eq!(1, far_stop_issues.len());
+    // geojson contains the stop and the shape of the trip
+    assert_eq!(
+        far_stop_issues[0].geojson.as_ref().unwrap().to_string(),
+        "{\"type\":\"FeatureCollection\",\"features\":[{\"type\":\"Feature\",\"geometry\":{\"type\":\"Point\",\"coordinates\":[-116.75,36.92]},\"properties\":{\"id\":\"C\",\"name\":\"Stop C\"}},{\"type\":\"Feature\",\"geometry\":{\"type\":\"LineString\",\"coordinates\":[[-116.76,36.9],[-116.76,36.91],[-116.76,36.92],[-116.76,36.93]]},\"properties\":{\"shape_id\":\"S1\"}}]}"
+    );
+
+    let endpoint_issues = validation
+        .validations
+        .get(&issues::IssueType::ShapeEndpointTooFarFromStop)
+        .unwrap();
+    // geojson contains the shape and the stop at its end
+    assert_eq!(
+        2,
+        endpoint_issues[0].geojson.as_ref().unwrap().features.len()
+    );
+}